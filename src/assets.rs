@@ -0,0 +1,102 @@
+use crate::error::Error;
+
+use log::{info, warn};
+use std::borrow::Cow;
+use std::fs;
+use std::path::PathBuf;
+
+pub const TEMPLATE: &str = "template.svg";
+
+// Built into the binary, so a kindle without an assets directory still has a dashboard
+const EMBEDDED: [(&str, &str); 17] = [
+    (TEMPLATE, include_str!("template.svg")),
+    ("icons/1.svg", include_str!("icons/1.svg")),
+    ("icons/2.svg", include_str!("icons/2.svg")),
+    ("icons/3.svg", include_str!("icons/3.svg")),
+    ("icons/4.svg", include_str!("icons/4.svg")),
+    ("icons/5.svg", include_str!("icons/5.svg")),
+    ("icons/6.svg", include_str!("icons/6.svg")),
+    ("icons/7.svg", include_str!("icons/7.svg")),
+    ("icons/8.svg", include_str!("icons/8.svg")),
+    ("moon/1.svg", include_str!("moon/1.svg")),
+    ("moon/2.svg", include_str!("moon/2.svg")),
+    ("moon/3.svg", include_str!("moon/3.svg")),
+    ("moon/4.svg", include_str!("moon/4.svg")),
+    ("moon/5.svg", include_str!("moon/5.svg")),
+    ("moon/6.svg", include_str!("moon/6.svg")),
+    ("moon/7.svg", include_str!("moon/7.svg")),
+    ("moon/8.svg", include_str!("moon/8.svg")),
+];
+
+/*
+    Template and icons, read from the assets directory on every render so a new layout
+    only needs an scp, not a redeploy. Anything missing there falls back to the embedded copy,
+    using the same relative paths as in src/ (template.svg, icons/3.svg, moon/5.svg).
+*/
+pub struct Assets {
+    dir: PathBuf,
+}
+
+impl Assets {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Assets { dir: dir.into() }
+    }
+
+    /// The user supplied version of `name`, if there is a readable one
+    pub fn custom(&self, name: &str) -> Option<String> {
+        let path = self.dir.join(name);
+        if !path.exists() {
+            return None;
+        }
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                info!("Using {}", path.display());
+                Some(contents)
+            }
+            Err(e) => {
+                warn!(
+                    "Could not read {}, using the built-in one: {e}",
+                    path.display()
+                );
+                None
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<Cow<'static, str>, Error> {
+        match self.custom(name) {
+            Some(contents) => Ok(Cow::Owned(contents)),
+            None => embedded(name).map(Cow::Borrowed),
+        }
+    }
+}
+
+pub fn embedded(name: &str) -> Result<&'static str, Error> {
+    EMBEDDED
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, contents)| *contents)
+        .ok_or_else(|| Error::Render(format!("no built-in asset {name}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_overrides_embedded() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::create_dir_all(dir.join("icons")).unwrap();
+        fs::write(dir.join("icons/2.svg"), "<svg id=\"mine\"/>").unwrap();
+
+        let assets = Assets::new(dir);
+        assert_eq!(assets.get("icons/2.svg").unwrap(), "<svg id=\"mine\"/>");
+        assert_eq!(
+            assets.get("icons/3.svg").unwrap(),
+            include_str!("icons/3.svg")
+        );
+        assert!(matches!(assets.get("icons/9.svg"), Err(Error::Render(_))));
+        assert!(assets.custom(TEMPLATE).is_none());
+    }
+}
@@ -0,0 +1,180 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{BatteryConfig, Config};
+use crate::error::Error;
+use crate::source::DataSource;
+
+use log::warn;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+// Where the kernel reports the charge, depending on the model and firmware
+const CAPACITY_PATHS: [&str; 4] = [
+    "/sys/class/power_supply/bd7181x_bat/capacity",
+    "/sys/class/power_supply/max77796-battery/capacity",
+    "/sys/devices/system/wario_battery/wario_battery0/battery_capacity",
+    "/sys/devices/system/yoshi_battery/yoshi_battery0/battery_capacity",
+];
+
+const CHARGING_PATHS: [&str; 4] = [
+    "/sys/class/power_supply/bd7181x_bat/status",
+    "/sys/class/power_supply/max77796-battery/status",
+    "/sys/devices/platform/aplite_charger.0/charging",
+    "/sys/devices/system/wario_charger/wario_charger0/charging",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Battery {
+    pub percent: u8,
+    pub charging: bool,
+}
+
+/*
+    The kindle's own battery. Reads the files set in `[battery]` if any, else the first
+    sysfs file this model has, else asks powerd through lipc-get-prop, and gasgauge-info
+    as a last resort on old firmwares.
+*/
+pub struct BatterySource {
+    config: BatteryConfig,
+}
+
+impl BatterySource {
+    pub fn new(config: &Config) -> Self {
+        BatterySource {
+            config: config.battery.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl DataSource for BatterySource {
+    type Output = Battery;
+    const NAME: &'static str = "battery";
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(5)
+    }
+
+    // Read on every fetch, it is local and cheap
+    fn refresh(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    async fn fetch(&self) -> Result<Self::Output, Error> {
+        let percent = match &self.config.capacity {
+            Some(path) => parse_percent(&read(path)?)?,
+            None => detect_percent()?,
+        };
+        let charging = match &self.config.charging {
+            Some(path) => parse_charging(&read(path)?),
+            None => detect_charging().unwrap_or_else(|| {
+                warn!("Could not tell whether the battery is charging");
+                false
+            }),
+        };
+        Ok(Battery { percent, charging })
+    }
+}
+
+fn read(path: &Path) -> Result<String, Error> {
+    std::fs::read_to_string(path)
+        .map_err(|e| Error::Io(format!("Could not read {}: {e}", path.display())))
+}
+
+fn first_existing(paths: &[&str]) -> Option<String> {
+    paths
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+}
+
+fn powerd_prop(name: &str) -> Option<String> {
+    let output = Command::new("lipc-get-prop")
+        .args(["com.lab126.powerd", name])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn detect_percent() -> Result<u8, Error> {
+    if let Some(capacity) = first_existing(&CAPACITY_PATHS).or_else(|| powerd_prop("battLevel")) {
+        return parse_percent(&capacity);
+    }
+    let output = Command::new("gasgauge-info")
+        .arg("-c")
+        .output()
+        .map_err(|e| {
+            Error::Io(format!(
+                "No battery found, and could not run gasgauge-info: {e}"
+            ))
+        })?;
+    parse_percent(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn detect_charging() -> Option<bool> {
+    first_existing(&CHARGING_PATHS)
+        .or_else(|| powerd_prop("isCharging"))
+        .map(|status| parse_charging(&status))
+}
+
+/// Accepts `85` as sysfs and lipc print it, or `85%` as gasgauge-info does
+fn parse_percent(value: &str) -> Result<u8, Error> {
+    let value = value.trim();
+    match value.trim_end_matches('%').trim().parse::<u8>() {
+        Ok(percent) if percent <= 100 => Ok(percent),
+        _ => Err(Error::Parse(format!("the battery level \"{value}\""))),
+    }
+}
+
+// sysfs status files say Charging, Discharging, Full..., the rest 1 or 0
+fn parse_charging(value: &str) -> bool {
+    matches!(value.trim(), "1" | "Charging" | "Full")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_percent("85\n").unwrap(), 85);
+        assert_eq!(parse_percent("7%\n").unwrap(), 7);
+        assert!(parse_percent("101").is_err());
+        assert!(parse_percent("").is_err());
+
+        assert!(parse_charging("Charging\n"));
+        assert!(parse_charging("1"));
+        assert!(!parse_charging("Discharging\n"));
+        assert!(!parse_charging("0"));
+    }
+
+    #[tokio::test]
+    async fn test_fixture_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("capacity"), "42\n").unwrap();
+        std::fs::write(dir.join("status"), "Charging\n").unwrap();
+
+        let source = BatterySource {
+            config: BatteryConfig {
+                capacity: Some(dir.join("capacity")),
+                charging: Some(dir.join("status")),
+                ..Default::default()
+            },
+        };
+        assert_eq!(
+            source.fetch().await.unwrap(),
+            Battery {
+                percent: 42,
+                charging: true
+            }
+        );
+
+        std::fs::remove_file(dir.join("capacity")).unwrap();
+        assert!(source.fetch().await.is_err());
+    }
+}
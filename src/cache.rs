@@ -0,0 +1,167 @@
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+use log::warn;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/*
+    Last good result of every source, one json file per source, so a restart
+    (or a failed fetch) does not mean an empty dashboard. Also keeps the bodies for
+    conditional requests, see `http::Http`.
+*/
+#[derive(Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry<T> {
+    fetched_at: DateTime<Utc>,
+    data: T,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Cache { dir: dir.into() }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.json"))
+    }
+
+    pub fn load<T: DeserializeOwned>(&self, name: &str) -> Option<(DateTime<Utc>, T)> {
+        let path = self.path(name);
+        let contents = fs::read(&path).ok()?;
+        match serde_json::from_slice::<Entry<T>>(&contents) {
+            Ok(entry) => Some((entry.fetched_at, entry.data)),
+            Err(e) => {
+                warn!("Ignoring unreadable cache {}: {e}", path.display());
+                None
+            }
+        }
+    }
+
+    pub fn store<T: Serialize>(
+        &self,
+        name: &str,
+        fetched_at: DateTime<Utc>,
+        data: &T,
+    ) -> Result<(), Error> {
+        let contents = serde_json::to_vec(&Entry { fetched_at, data })
+            .map_err(|e| Error::Io(format!("Could not serialize {name}: {e}")))?;
+
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| Error::Io(format!("Could not create {}: {e}", self.dir.display())))?;
+
+        // Write then rename, so losing power halfway never leaves a truncated cache behind
+        let path = self.path(name);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, contents)
+            .map_err(|e| Error::Io(format!("Could not write {}: {e}", tmp.display())))?;
+        fs::rename(&tmp, &path)
+            .map_err(|e| Error::Io(format!("Could not write {}: {e}", path.display())))?;
+        Ok(())
+    }
+
+    /// Marks entry `name` as still in use, so `remove_older_than` keeps it
+    pub fn touch(&self, name: &str) -> Result<(), Error> {
+        let path = self.path(name);
+        fs::File::options()
+            .append(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()))
+            .map_err(|e| Error::Io(format!("Could not touch {}: {e}", path.display())))
+    }
+
+    /// Removes entry `name`, if there is one
+    pub fn remove(&self, name: &str) -> Result<(), Error> {
+        let path = self.path(name);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(Error::Io(format!(
+                "Could not remove {}: {e}",
+                path.display()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the entries that were not stored again or touched within `age`, for names
+    /// that went out of use
+    pub fn remove_older_than(&self, age: Duration) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let old = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|elapsed| elapsed > age);
+            if old {
+                if let Err(e) = fs::remove_file(entry.path()) {
+                    warn!("Could not remove {}: {e}", entry.path().display());
+                }
+            }
+        }
+    }
+}
+
+/// Serde helpers to store an image as a base64 png, for `#[serde(with = "cache::png")]`
+pub mod png {
+    use base64::prelude::*;
+    use image::{DynamicImage, ImageFormat};
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::io::Cursor;
+
+    pub fn serialize<S: Serializer>(
+        image: &DynamicImage,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut buffer = Cursor::new(Vec::new());
+        image
+            .write_to(&mut buffer, ImageFormat::Png)
+            .map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&BASE64_STANDARD.encode(buffer.get_ref()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DynamicImage, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let bytes = BASE64_STANDARD.decode(encoded).map_err(de::Error::custom)?;
+        image::load_from_memory_with_format(&bytes, ImageFormat::Png).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+        assert!(cache.load::<Vec<u32>>("numbers").is_none());
+
+        let now = Utc::now();
+        cache.store("numbers", now, &vec![1, 2, 3]).unwrap();
+        let (fetched_at, numbers) = cache.load::<Vec<u32>>("numbers").unwrap();
+        assert_eq!(fetched_at, now);
+        assert_eq!(numbers, vec![1, 2, 3]);
+
+        // Wrong shape is treated as a miss rather than an error
+        assert!(cache.load::<String>("numbers").is_none());
+
+        cache.remove_older_than(Duration::from_secs(60));
+        assert!(cache.load::<Vec<u32>>("numbers").is_some());
+        cache.remove_older_than(Duration::ZERO);
+        assert!(cache.load::<Vec<u32>>("numbers").is_none());
+    }
+}
@@ -0,0 +1,184 @@
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+use chrono_tz::Tz;
+
+use crate::weather::{day_name, HourData};
+
+use std::fmt::Write;
+
+// Size of the fragment in svg units, the template can scale it with a transform
+pub const WIDTH: f64 = 760.0;
+pub const HEIGHT: f64 = 190.0;
+
+// Room for the temperature labels on the left, the day names on top and the hours below
+const LEFT: f64 = 56.0;
+const RIGHT: f64 = 14.0;
+const TOP: f64 = 34.0;
+const BOTTOM: f64 = 30.0;
+
+// Rain in 3 hours that fills the whole height, heavier rain is cut off
+const FULL_RAIN_MM: f64 = 6.0;
+
+// Width a day's name needs, next to its line
+const DAY_LABEL: f64 = 50.0;
+
+const FONT: &str = "font-family:FreeSans;font-size:20px;fill:#000000";
+
+/*
+    The next `hours` of the forecast as an svg fragment: temperature as a line, rain as
+    bars, and a dashed line with the day's name where a new day starts. Drawn from (0, 0)
+    to (WIDTH, HEIGHT) on a white card, the template decides where it goes.
+*/
+pub fn hourly(points: &[HourData], now: &DateTime<Tz>, hours: u32) -> Option<String> {
+    let tz = now.timezone();
+    let now = now.with_timezone(&Utc);
+    // The point that covers now, openweathermap's are 3 hours apart
+    let start = points
+        .iter()
+        .map(|p| p.time)
+        .filter(|time| *time <= now)
+        .max()
+        .unwrap_or(now);
+    let end = start + Duration::hours(hours as i64);
+    let points: Vec<_> = points
+        .iter()
+        .filter(|p| p.time >= start && p.time <= end)
+        .collect();
+    if points.len() < 2 {
+        return None;
+    }
+
+    let min = points
+        .iter()
+        .map(|p| p.temp_c)
+        .fold(f64::INFINITY, f64::min)
+        .floor();
+    let max = points
+        .iter()
+        .map(|p| p.temp_c)
+        .fold(f64::NEG_INFINITY, f64::max)
+        .ceil();
+    // A flat day still gets a little slope to draw on
+    let (min, max) = if max - min < 2.0 {
+        (min - 1.0, max + 1.0)
+    } else {
+        (min, max)
+    };
+
+    let plot_width = WIDTH - LEFT - RIGHT;
+    let plot_height = HEIGHT - TOP - BOTTOM;
+    let bottom = TOP + plot_height;
+    let span = (end - start).num_seconds() as f64;
+    let x = |time: DateTime<Utc>| LEFT + (time - start).num_seconds() as f64 / span * plot_width;
+    let y = |temp: f64| TOP + (max - temp) / (max - min) * plot_height;
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        r#"<rect width="{WIDTH}" height="{HEIGHT}" rx="17" style="fill:#ffffff;fill-opacity:0.9" />"#
+    );
+
+    // Rain fell over the 3 hours up to each point
+    for point in points.iter().filter(|p| p.rain_mm > 0.0) {
+        let left = x(point.time - Duration::hours(3)).max(LEFT);
+        let right = x(point.time);
+        let height = (point.rain_mm / FULL_RAIN_MM).min(1.0) * plot_height;
+        let _ = write!(
+            svg,
+            r#"<rect x="{left:.1}" y="{:.1}" width="{:.1}" height="{height:.1}" style="fill:#a0a0a0" />"#,
+            bottom - height,
+            (right - left - 2.0).max(1.0)
+        );
+    }
+
+    // New days and every 6 hours, on the local clock
+    let local_start = start.with_timezone(&tz);
+    let mut hour = local_start
+        .with_minute(0)
+        .and_then(|t| t.with_second(0))
+        .unwrap_or(local_start);
+    while hour.with_timezone(&Utc) <= end {
+        let hx = x(hour.with_timezone(&Utc));
+        if hx >= LEFT {
+            if hour.hour() == 0 {
+                let _ = write!(
+                    svg,
+                    r#"<line x1="{hx:.1}" y1="{:.1}" x2="{hx:.1}" y2="{bottom:.1}" style="stroke:#000000;stroke-width:2;stroke-dasharray:6,4" />"#,
+                    TOP - 24.0
+                );
+                // Not where it would not fit
+                if hx + DAY_LABEL < WIDTH - RIGHT {
+                    let _ = write!(
+                        svg,
+                        r#"<text x="{:.1}" y="{:.1}" style="{FONT};font-weight:bold">{}</text>"#,
+                        hx + 6.0,
+                        TOP - 8.0,
+                        day_name(hour.weekday())
+                    );
+                }
+            } else if hour.hour() % 6 == 0 {
+                let _ = write!(
+                    svg,
+                    r#"<text x="{hx:.1}" y="{:.1}" text-anchor="middle" style="{FONT}">{:02}</text>"#,
+                    HEIGHT - 8.0,
+                    hour.hour()
+                );
+            }
+        }
+        hour += Duration::hours(1);
+    }
+
+    let line: Vec<String> = points
+        .iter()
+        .map(|p| format!("{:.1},{:.1}", x(p.time), y(p.temp_c)))
+        .collect();
+    let _ = write!(
+        svg,
+        r#"<polyline points="{}" style="fill:none;stroke:#000000;stroke-width:4;stroke-linejoin:round;stroke-linecap:round" />"#,
+        line.join(" ")
+    );
+
+    for (temp, ty) in [(max, TOP), (min, bottom)] {
+        let _ = write!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="end" style="{FONT}">{temp:.0}°</text>"#,
+            LEFT - 8.0,
+            ty + 7.0
+        );
+    }
+    Some(svg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn points(count: i64) -> Vec<HourData> {
+        let start = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        (0..count)
+            .map(|i| HourData {
+                time: start + Duration::hours(3 * i),
+                temp_c: 18.0 + i as f64 % 4.0,
+                rain_mm: if i % 5 == 0 { 1.5 } else { 0.0 },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hourly() {
+        let now = chrono_tz::Atlantic::Canary
+            .with_ymd_and_hms(2024, 6, 1, 10, 15, 0)
+            .unwrap();
+        let svg = hourly(&points(24), &now, 48).unwrap();
+        let document = format!(r#"<svg xmlns="http://www.w3.org/2000/svg">{svg}</svg>"#);
+        assert!(usvg::Tree::from_str(&document, &usvg::Options::default()).is_ok());
+
+        // Two local midnights in the next 48 hours
+        assert_eq!(svg.matches("stroke-dasharray").count(), 2);
+        assert!(svg.contains(">Dom<"));
+        assert!(svg.contains("<polyline"));
+
+        // Not enough of the forecast left to draw a line
+        assert!(hourly(&points(4), &now, 48).is_none());
+    }
+}
@@ -0,0 +1,553 @@
+use chrono::NaiveTime;
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use crate::device;
+use crate::display::Backend;
+use crate::dither::Dither;
+use crate::error::Error;
+use crate::source::SOURCE_NAMES;
+
+use log::{info, warn};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/*
+    Everything that used to be hardcoded for Lanzarote lives here, so the same
+    binary can be dropped on any kindle next to its own config.toml.
+
+    Every field has a default matching the original hardcoded value.
+*/
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub sleep_minutes: u32,
+    /// How often to switch to the next page, defaults to `sleep_minutes`
+    pub rotate_minutes: Option<u32>,
+    pub timezone: Tz,
+    /// Names of the data sources to fetch, see `source::SOURCE_NAMES`
+    pub sources: Vec<String>,
+    /// Api keys of the sources, see `secrets::Secrets`
+    pub secrets: PathBuf,
+    pub weather: WeatherConfig,
+    pub wind: WindConfig,
+    pub tides: TidesConfig,
+    pub cache: CacheConfig,
+    pub assets: AssetsConfig,
+    pub display: DisplayConfig,
+    pub device: DeviceConfig,
+    pub battery: BatteryConfig,
+    pub power: PowerConfig,
+    pub network: NetworkConfig,
+    pub http: HttpConfig,
+    /// Rotated through in order, see `pages::Rotation`
+    pub pages: Vec<PageConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct WeatherConfig {
+    pub lat: f64,
+    pub lon: f64,
+    /// Hours of forecast the hourly chart shows
+    pub chart_hours: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindConfig {
+    /// AEMET conventional observation station id
+    pub station: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TidesConfig {
+    /// Instituto Hidrográfico de la Marina port id
+    pub port_id: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Where the last good result of every source is kept
+    pub dir: PathBuf,
+    /// Per source override of how long a result is fresh, keyed by source name
+    pub ttl_minutes: HashMap<String, u64>,
+    /// Warn on the dashboard when the disk the cache is on has less free space than this
+    pub min_free_mb: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AssetsConfig {
+    /// Overrides for template.svg, icons/*.svg and moon/*.svg, read on every render
+    pub dir: PathBuf,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    pub backend: Backend,
+    /// Framebuffer device for the `framebuffer` backend
+    pub device: PathBuf,
+    /// Where the `png` and `eips` backends save the frame
+    pub output: PathBuf,
+    /// Also save the filled in template here, to work on it
+    pub svg: Option<PathBuf>,
+    /// Flash the whole panel every this many updates, the rest only redraw what changed
+    pub full_refresh_every: u32,
+    /// Gray levels the panel can show, 4 or 16, the device's if not set
+    pub gray_levels: Option<u8>,
+    /// How bitmaps like the radar map are brought down to `gray_levels`
+    pub dither: Dither,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceConfig {
+    /// `auto` to detect it, or one of `device::models()`
+    pub model: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatteryConfig {
+    /// File with the charge in percent, detected from the model if not set
+    pub capacity: Option<PathBuf>,
+    /// File saying whether it is charging, detected from the model if not set
+    pub charging: Option<PathBuf>,
+    /// Below this the template shows a low battery warning
+    pub low_percent: u8,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerConfig {
+    /// Suspend the device between refreshes instead of keeping it awake
+    pub low_power: bool,
+    /// The rtc's wakealarm file, the first of rtc1 and rtc0 that exists if not set
+    pub wakealarm: Option<PathBuf>,
+    /// Written `mem` to suspend
+    pub state: PathBuf,
+    /// Turn Wi-Fi off while suspended
+    pub toggle_wifi: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Turn Wi-Fi on and wait for it to connect before fetching
+    pub manage_wifi: bool,
+    /// Wireless interface to wait on
+    pub interface: String,
+    pub connect_timeout_seconds: u64,
+    /// Fetched to tell whether the kindle is online
+    pub probe_url: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// For every attempt of a request
+    pub timeout_seconds: u64,
+    /// Extra attempts after a network error or an overloaded server
+    pub retries: u32,
+    /// Wait before the first retry, doubled for every one after it
+    pub backoff_seconds: u64,
+    pub user_agent: String,
+    /// e.g. `http://proxy:3128` or `socks5://proxy:1080`
+    pub proxy: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PageConfig {
+    /// Only used in logs
+    pub name: String,
+    /// Path inside the assets directory, the built-in template.svg if it is missing
+    pub template: String,
+    /// Sources the template can use, all enabled ones if not set
+    pub sources: Option<Vec<String>>,
+    /// Only shown from this local time...
+    pub from: Option<NaiveTime>,
+    /// ...until this one, can wrap around midnight
+    pub until: Option<NaiveTime>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            sleep_minutes: 60,
+            rotate_minutes: None,
+            timezone: chrono_tz::Atlantic::Canary,
+            sources: SOURCE_NAMES.iter().map(|s| s.to_string()).collect(),
+            secrets: PathBuf::from("sensitive/secrets.toml"),
+            weather: WeatherConfig::default(),
+            wind: WindConfig::default(),
+            tides: TidesConfig::default(),
+            cache: CacheConfig::default(),
+            assets: AssetsConfig::default(),
+            display: DisplayConfig::default(),
+            device: DeviceConfig::default(),
+            battery: BatteryConfig::default(),
+            power: PowerConfig::default(),
+            network: NetworkConfig::default(),
+            http: HttpConfig::default(),
+            pages: vec![PageConfig::default()],
+        }
+    }
+}
+
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        WeatherConfig {
+            lat: 28.96302,
+            lon: -13.54769,
+            chart_hours: 36,
+        }
+    }
+}
+
+impl Default for WindConfig {
+    fn default() -> Self {
+        WindConfig {
+            station: "C029O".to_string(),
+        }
+    }
+}
+
+impl Default for TidesConfig {
+    fn default() -> Self {
+        TidesConfig { port_id: 53 }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            dir: PathBuf::from("cache"),
+            ttl_minutes: HashMap::new(),
+            min_free_mb: 10,
+        }
+    }
+}
+
+impl Default for AssetsConfig {
+    fn default() -> Self {
+        AssetsConfig {
+            dir: PathBuf::from("assets"),
+        }
+    }
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            backend: Backend::Auto,
+            device: PathBuf::from("/dev/fb0"),
+            output: PathBuf::from("output.png"),
+            svg: None,
+            full_refresh_every: 10,
+            gray_levels: None,
+            dither: Dither::FloydSteinberg,
+        }
+    }
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        DeviceConfig {
+            model: "auto".to_string(),
+        }
+    }
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        BatteryConfig {
+            capacity: None,
+            charging: None,
+            low_percent: 20,
+        }
+    }
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        PowerConfig {
+            low_power: false,
+            wakealarm: None,
+            state: PathBuf::from("/sys/power/state"),
+            toggle_wifi: true,
+        }
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            manage_wifi: true,
+            interface: "wlan0".to_string(),
+            connect_timeout_seconds: 60,
+            probe_url: "http://www.google.com".to_string(),
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            timeout_seconds: 15,
+            retries: 2,
+            backoff_seconds: 2,
+            user_agent: crate::http::USER_AGENT.to_string(),
+            proxy: None,
+        }
+    }
+}
+
+impl Default for PageConfig {
+    fn default() -> Self {
+        PageConfig {
+            name: "dashboard".to_string(),
+            template: crate::assets::TEMPLATE.to_string(),
+            sources: None,
+            from: None,
+            until: None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads and validates the config at `path`.
+    ///
+    /// A missing file is only tolerated for the default path, in which case the built-in
+    /// (Lanzarote) defaults are used.
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        if !path.exists() {
+            if path == Path::new(DEFAULT_CONFIG_PATH) {
+                warn!("No {DEFAULT_CONFIG_PATH} found, using built-in defaults");
+                return Ok(Config::default());
+            }
+            return Err(Error::Config(format!(
+                "Config file {} does not exist",
+                path.display()
+            )));
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("Could not read {}: {e}", path.display())))?;
+        let config = Config::parse(&contents)
+            .map_err(|e| Error::Config(format!("Invalid {}: {e}", path.display())))?;
+
+        info!("Loaded config from {}", path.display());
+        Ok(config)
+    }
+
+    pub fn rotate_minutes(&self) -> u32 {
+        self.rotate_minutes.unwrap_or(self.sleep_minutes)
+    }
+
+    pub fn parse(contents: &str) -> Result<Config, String> {
+        let config: Config = toml::from_str(contents).map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.sleep_minutes == 0 || 60 % self.sleep_minutes != 0 {
+            return Err(format!(
+                "sleep_minutes must divide an hour evenly (1, 2, 3, 4, 5, 6, 10, 12, 15, 20, 30 or 60), got {}",
+                self.sleep_minutes
+            ));
+        }
+        if let Some(rotate) = self.rotate_minutes {
+            if rotate == 0 || !self.sleep_minutes.is_multiple_of(rotate) {
+                return Err(format!(
+                    "rotate_minutes must divide sleep_minutes ({}) evenly, got {rotate}",
+                    self.sleep_minutes
+                ));
+            }
+        }
+        for name in &self.sources {
+            if !SOURCE_NAMES.contains(&name.as_str()) {
+                return Err(format!(
+                    "unknown source \"{name}\" in sources, expected one of: {}",
+                    SOURCE_NAMES.join(", ")
+                ));
+            }
+        }
+        for name in self.cache.ttl_minutes.keys() {
+            if !SOURCE_NAMES.contains(&name.as_str()) {
+                return Err(format!(
+                    "unknown source \"{name}\" in cache.ttl_minutes, expected one of: {}",
+                    SOURCE_NAMES.join(", ")
+                ));
+            }
+        }
+        if !(-90.0..=90.0).contains(&self.weather.lat) {
+            return Err(format!(
+                "weather.lat must be between -90 and 90, got {}",
+                self.weather.lat
+            ));
+        }
+        if !(-180.0..=180.0).contains(&self.weather.lon) {
+            return Err(format!(
+                "weather.lon must be between -180 and 180, got {}",
+                self.weather.lon
+            ));
+        }
+        if self.wind.station.trim().is_empty() {
+            return Err("wind.station must not be empty".to_string());
+        }
+        if self.display.full_refresh_every == 0 {
+            return Err(
+                "display.full_refresh_every must be at least 1, which always refreshes fully"
+                    .to_string(),
+            );
+        }
+        if let Some(levels) = self.display.gray_levels {
+            if ![4, 16].contains(&levels) {
+                return Err(format!("display.gray_levels must be 4 or 16, got {levels}"));
+            }
+        }
+        let models = device::models();
+        if self.device.model != "auto" && !models.contains(&self.device.model.as_str()) {
+            return Err(format!(
+                "unknown device.model \"{}\", expected auto or one of: {}",
+                self.device.model,
+                models.join(", ")
+            ));
+        }
+        if self.battery.low_percent > 100 {
+            return Err(format!(
+                "battery.low_percent must be at most 100, got {}",
+                self.battery.low_percent
+            ));
+        }
+        // The forecast only reaches 5 days ahead, in 3 hour steps
+        if !(6..=120).contains(&self.weather.chart_hours) {
+            return Err(format!(
+                "weather.chart_hours must be between 6 and 120, got {}",
+                self.weather.chart_hours
+            ));
+        }
+        if self.http.timeout_seconds == 0 {
+            return Err("http.timeout_seconds must be at least 1".to_string());
+        }
+        if self.network.probe_url.trim().is_empty() {
+            return Err("network.probe_url must not be empty".to_string());
+        }
+        if self.pages.is_empty() {
+            return Err("pages must not be empty, leave it out for the default page".to_string());
+        }
+        for page in &self.pages {
+            if page.template.trim().is_empty() {
+                return Err(format!("page \"{}\" has an empty template", page.name));
+            }
+            if page.from.is_some() != page.until.is_some() {
+                return Err(format!(
+                    "page \"{}\" needs both from and until, or neither",
+                    page.name
+                ));
+            }
+            for name in page.sources.iter().flatten() {
+                if !self.sources.contains(name) {
+                    return Err(format!(
+                        "page \"{}\" uses source \"{name}\", which is not enabled in sources",
+                        page.name
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_config_is_default() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config.sleep_minutes, 60);
+        assert_eq!(config.timezone, chrono_tz::Atlantic::Canary);
+        assert_eq!(config.wind.station, "C029O");
+        assert_eq!(config.tides.port_id, 53);
+        assert_eq!(config.sources.len(), SOURCE_NAMES.len());
+        assert_eq!(config.pages.len(), 1);
+        assert_eq!(config.pages[0].template, "template.svg");
+    }
+
+    #[test]
+    fn test_pages() {
+        let config = Config::parse(
+            r#"
+            sleep_minutes = 30
+            rotate_minutes = 10
+
+            [[pages]]
+            name = "weather"
+
+            [[pages]]
+            name = "night"
+            template = "night.svg"
+            sources = ["moon", "tides"]
+            from = "22:00"
+            until = "06:30"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.pages.len(), 2);
+        assert_eq!(config.pages[0].template, "template.svg");
+        assert!(config.pages[0].sources.is_none());
+        assert_eq!(config.pages[1].from, NaiveTime::from_hms_opt(22, 0, 0));
+    }
+
+    #[test]
+    fn test_partial_config() {
+        let config = Config::parse(
+            r#"
+            sleep_minutes = 15
+            timezone = "Europe/Madrid"
+
+            [weather]
+            lat = 40.4168
+            lon = -3.7038
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.sleep_minutes, 15);
+        assert_eq!(config.timezone, chrono_tz::Europe::Madrid);
+        assert_eq!(config.display.backend, Backend::Auto);
+        assert_eq!(config.weather.lat, 40.4168);
+        assert_eq!(config.tides.port_id, 53);
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(Config::parse("sleep_minutes = 7").is_err());
+        assert!(Config::parse("timezone = \"Mars/Olympus\"").is_err());
+        assert!(Config::parse("[weather]\nlat = 128.0").is_err());
+        assert!(Config::parse("[wind]\nstation = \"\"").is_err());
+        assert!(Config::parse("sleep_minuts = 15").is_err());
+        assert!(Config::parse("sources = [\"weather\", \"horoscope\"]").is_err());
+        assert!(Config::parse("[cache.ttl_minutes]\nhoroscope = 5").is_err());
+        assert!(Config::parse("rotate_minutes = 7").is_err());
+        assert!(Config::parse("pages = []").is_err());
+        assert!(Config::parse("[display]\nbackend = \"hdmi\"").is_err());
+        assert!(Config::parse("[display]\nfull_refresh_every = 0").is_err());
+        assert!(Config::parse("[display]\ngray_levels = 8").is_err());
+        assert!(Config::parse("[device]\nmodel = \"kindle2\"").is_err());
+        assert!(Config::parse("[device]\nmodel = \"pw3\"").is_ok());
+        assert!(Config::parse("[display]\ndither = \"atkinson\"").is_err());
+        assert!(Config::parse("[http]\ntimeout_seconds = 0").is_err());
+        assert!(Config::parse("[weather]\nchart_hours = 240").is_err());
+        assert!(Config::parse("[[pages]]\nfrom = \"08:00\"").is_err());
+        assert!(Config::parse("sources = [\"moon\"]\n[[pages]]\nsources = [\"wind\"]").is_err());
+    }
+}
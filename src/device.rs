@@ -0,0 +1,404 @@
+use regex::Regex;
+
+use log::{info, warn};
+use std::process::Command;
+
+pub const USID_PATH: &str = "/proc/usid";
+pub const CPUINFO_PATH: &str = "/proc/cpuinfo";
+
+/// What the renderer needs to know about the panel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub name: &'static str,
+    /// Native framebuffer size, portrait on every kindle
+    pub width: u32,
+    pub height: u32,
+    /// Pixels per inch, so sizes in `mm` or `pt` come out right on the panel
+    pub dpi: u32,
+    pub gray_levels: u8,
+    /// Whether the landscape dashboard is turned a quarter onto a portrait panel
+    pub rotate: bool,
+    /// Whether the epdc can refresh part of the panel without flashing all of it
+    pub partial_refresh: bool,
+    /// Whether it has the REAGL waveform, which keeps partial refreshes from ghosting
+    /// much. Without it the panel needs a full refresh more often
+    pub reagl: bool,
+    /// Whether its epdc takes the update the framebuffer backend sends, see `display`.
+    /// Only known for the touch and paperwhite 1 layout, the rest go through eips
+    pub framebuffer: bool,
+}
+
+struct Profile {
+    /// Used for `device.model` in the config
+    id: &'static str,
+    device: Device,
+    /// Device codes in the serial number in /proc/usid, two characters from the
+    /// third one for older models, three from the fourth one since the paperwhite 3,
+    /// see `from_serial`
+    codes: &'static [&'static str],
+}
+
+// Device codes as collected by KOReader's kindle support. Every kindle since the touch
+// shows 16 grays and refreshes parts of the panel
+const PROFILES: [Profile; 10] = [
+    Profile {
+        id: "kt2",
+        device: Device {
+            name: "Kindle Basic (KT2)",
+            width: 600,
+            height: 800,
+            dpi: 167,
+            gray_levels: 16,
+            rotate: true,
+            partial_refresh: true,
+            reagl: false,
+            framebuffer: false,
+        },
+        codes: &["C6", "DD"],
+    },
+    Profile {
+        id: "pw1",
+        device: Device {
+            name: "Kindle Paperwhite",
+            width: 758,
+            height: 1024,
+            dpi: 212,
+            gray_levels: 16,
+            rotate: true,
+            partial_refresh: true,
+            reagl: false,
+            framebuffer: true,
+        },
+        codes: &["24", "1B", "1D", "1F", "1C", "20"],
+    },
+    Profile {
+        id: "pw2",
+        device: Device {
+            name: "Kindle Paperwhite 2",
+            width: 758,
+            height: 1024,
+            dpi: 212,
+            gray_levels: 16,
+            rotate: true,
+            partial_refresh: true,
+            reagl: true,
+            framebuffer: false,
+        },
+        codes: &[
+            "D4", "5A", "D5", "D6", "D7", "D8", "F2", "17", "60", "F4", "F9", "62", "61", "5F",
+        ],
+    },
+    Profile {
+        id: "voyage",
+        device: Device {
+            name: "Kindle Voyage",
+            width: 1072,
+            height: 1448,
+            dpi: 300,
+            gray_levels: 16,
+            rotate: true,
+            partial_refresh: true,
+            reagl: true,
+            framebuffer: false,
+        },
+        codes: &["13", "54", "2A", "4F", "52", "53"],
+    },
+    Profile {
+        id: "pw3",
+        device: Device {
+            name: "Kindle Paperwhite 3",
+            width: 1072,
+            height: 1448,
+            dpi: 300,
+            gray_levels: 16,
+            rotate: true,
+            partial_refresh: true,
+            reagl: true,
+            framebuffer: false,
+        },
+        codes: &[
+            "0G1", "0G2", "0G4", "0G5", "0G6", "0G7", "0KB", "0KC", "0KD", "0KE", "0KF", "0KG",
+            "0LK", "0LL",
+        ],
+    },
+    Profile {
+        id: "oasis",
+        device: Device {
+            name: "Kindle Oasis",
+            width: 1072,
+            height: 1448,
+            dpi: 300,
+            gray_levels: 16,
+            rotate: true,
+            partial_refresh: true,
+            reagl: true,
+            framebuffer: false,
+        },
+        codes: &["0GC", "0GD", "0GR", "0GS", "0GT", "0GU"],
+    },
+    Profile {
+        id: "oasis2",
+        device: Device {
+            name: "Kindle Oasis 2/3",
+            width: 1264,
+            height: 1680,
+            dpi: 300,
+            gray_levels: 16,
+            rotate: true,
+            partial_refresh: true,
+            reagl: true,
+            framebuffer: false,
+        },
+        codes: &[
+            "0LM", "0LN", "0LP", "0LQ", "0P1", "0P2", "0P6", "0P7", "0P8", "0S1", "0S2", "0S3",
+            "0S4", "0S7", "0SA", "11L", "0WQ", "0WP", "0WN", "0WM", "0WL",
+        ],
+    },
+    Profile {
+        id: "pw4",
+        device: Device {
+            name: "Kindle Paperwhite 4",
+            width: 1072,
+            height: 1448,
+            dpi: 300,
+            gray_levels: 16,
+            rotate: true,
+            partial_refresh: true,
+            reagl: true,
+            framebuffer: false,
+        },
+        codes: &[
+            "0PP", "0T1", "0T2", "0T3", "0T4", "0T5", "0T6", "0T7", "0TJ", "0TK", "0TL", "0TM",
+            "0TN", "102", "103", "16Q", "16R", "16S", "16T", "16U", "16V",
+        ],
+    },
+    Profile {
+        id: "pw5",
+        device: Device {
+            name: "Kindle Paperwhite 5",
+            width: 1236,
+            height: 1648,
+            dpi: 300,
+            gray_levels: 16,
+            rotate: true,
+            partial_refresh: true,
+            reagl: true,
+            framebuffer: false,
+        },
+        codes: &[
+            "1LG", "1Q0", "1PX", "1VD", "219", "21A", "2BH", "2BJ", "2DK",
+        ],
+    },
+    Profile {
+        id: "scribe",
+        device: Device {
+            name: "Kindle Scribe",
+            width: 1860,
+            height: 2480,
+            dpi: 300,
+            gray_levels: 16,
+            rotate: true,
+            partial_refresh: true,
+            reagl: true,
+            framebuffer: false,
+        },
+        codes: &["23L", "23M", "270"],
+    },
+];
+
+/// Runs unrotated at a fixed size, for working on the dashboard without a kindle
+const DESKTOP: Device = Device {
+    name: "Desktop",
+    width: 600,
+    height: 800,
+    dpi: 96,
+    gray_levels: 16,
+    rotate: false,
+    partial_refresh: false,
+    reagl: false,
+    framebuffer: false,
+};
+
+// Board names in the Hardware line of /proc/cpuinfo of kindles with a 5.x firmware
+const KINDLE_BOARDS: [&str; 8] = [
+    "Yoshi",
+    "Wario",
+    "Duet",
+    "Heisenberg",
+    "Zelda",
+    "Rex",
+    "Bellatrix",
+    "Mario",
+];
+
+/// `model` values the config accepts besides `auto`
+pub fn models() -> Vec<&'static str> {
+    let mut models: Vec<&str> = PROFILES.iter().map(|p| p.id).collect();
+    models.push("desktop");
+    models
+}
+
+fn by_model(model: &str) -> Option<Device> {
+    if model == "desktop" {
+        return Some(DESKTOP);
+    }
+    PROFILES
+        .iter()
+        .find(|p| p.id == model)
+        .map(|p| p.device.clone())
+}
+
+/// The profile matching a kindle serial number
+pub fn from_serial(serial: &str) -> Option<Device> {
+    let serial = serial.trim();
+    // The prefix tells the two formats apart, the codes of one can turn up in the other's spot
+    let code = if serial.starts_with("B0") || serial.starts_with("90") {
+        serial.get(2..4)?
+    } else if serial.starts_with('G') {
+        serial.get(3..6)?
+    } else {
+        return None;
+    };
+    PROFILES
+        .iter()
+        .find(|p| p.codes.contains(&code))
+        .map(|p| p.device.clone())
+}
+
+/// The kindle board named in /proc/cpuinfo, if this looks like one
+pub fn kindle_board(cpuinfo: &str) -> Option<&'static str> {
+    let hardware = cpuinfo.lines().find(|l| l.starts_with("Hardware"))?;
+    KINDLE_BOARDS.into_iter().find(|b| hardware.contains(b))
+}
+
+fn xrandr_size() -> Option<(u32, u32)> {
+    // Run xrandr to get screen data
+    let output = Command::new("xrandr").output();
+
+    match output {
+        Ok(output) => {
+            let output_str = String::from_utf8_lossy(&output.stdout);
+
+            // regex to get the current screen size
+            let re = Regex::new(r"current (\d+) x (\d+)").expect("Failed to compile regex");
+
+            if let Some(caps) = re.captures(&output_str) {
+                let width = caps.get(1).map_or("", |m| m.as_str()).parse::<u32>();
+                let height = caps.get(2).map_or("", |m| m.as_str()).parse::<u32>();
+
+                match width {
+                    Ok(width) => match height {
+                        Ok(height) => Some((width, height)),
+                        Err(e) => {
+                            warn!("Could not determine screen size (height) from: {output_str} due to {e}");
+                            None
+                        }
+                    },
+                    Err(e) => {
+                        warn!(
+                            "Could not determine screen size (width) from: {output_str} due to {e}"
+                        );
+                        None
+                    }
+                }
+            } else {
+                None
+            }
+        }
+
+        Err(e) => {
+            warn!("Could not run xrandr to get screen size: {e}");
+            None
+        }
+    }
+}
+
+/*
+    Picks the panel to render for: the model set in the config, else the desktop one
+    with NOT_KINDLE set, else whatever /proc/usid says. An unknown kindle gets its size
+    from xrandr, falling back to the original 600x800 kindle.
+*/
+pub fn resolve(model: &str) -> Device {
+    let device = if let Some(device) = by_model(model) {
+        device
+    } else if std::env::var("NOT_KINDLE").is_ok() {
+        DESKTOP
+    } else if let Some(device) = std::fs::read_to_string(USID_PATH)
+        .ok()
+        .and_then(|serial| from_serial(&serial))
+    {
+        device
+    } else {
+        match std::fs::read_to_string(CPUINFO_PATH)
+            .ok()
+            .as_deref()
+            .and_then(kindle_board)
+        {
+            Some(board) => warn!("Unknown kindle on a {board} board, set device.model"),
+            None => warn!("This does not look like a kindle, set device.model or NOT_KINDLE"),
+        }
+        let (width, height) = xrandr_size().unwrap_or_else(|| {
+            warn!("Could not determine screen size, switching to 600x800");
+            (600, 800)
+        });
+        Device {
+            name: "Unknown kindle",
+            width,
+            height,
+            dpi: 167,
+            gray_levels: 16,
+            rotate: true,
+            partial_refresh: true,
+            reagl: false,
+            framebuffer: false,
+        }
+    };
+
+    info!(
+        "Rendering for a {} ({}x{}, {} dpi, {} grays)",
+        device.name, device.width, device.height, device.dpi, device.gray_levels
+    );
+    device
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_serial() {
+        assert_eq!(
+            from_serial("B0D4123456789ABC").unwrap().name,
+            "Kindle Paperwhite 2"
+        );
+        assert_eq!(
+            from_serial("90C61234567890AB").unwrap().name,
+            "Kindle Basic (KT2)"
+        );
+        assert_eq!(
+            from_serial("G090G1234567890\n").unwrap().name,
+            "Kindle Paperwhite 3"
+        );
+        assert_eq!(from_serial("G0023L1234567890").unwrap().width, 1860);
+        // Old serials that read like a paperwhite 5 and 4 code from the fourth character
+        assert_eq!(
+            from_serial("B0F219ABCDEF0123").unwrap().name,
+            "Kindle Paperwhite 2"
+        );
+        assert_eq!(
+            from_serial("B061021234567890").unwrap().name,
+            "Kindle Paperwhite 2"
+        );
+        assert!(from_serial("G000ZZZ123456789").is_none());
+        assert!(from_serial("X0D4123456789ABC").is_none());
+        assert!(from_serial("").is_none());
+    }
+
+    #[test]
+    fn test_kindle_board() {
+        let cpuinfo =
+            "Processor\t: ARMv7\nHardware\t: Freescale i.MX 6SoloLite based Wario Board\n";
+        assert_eq!(kindle_board(cpuinfo), Some("Wario"));
+        assert_eq!(kindle_board("Hardware\t: BCM2835\n"), None);
+    }
+}
@@ -0,0 +1,40 @@
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// Bytes free for us on the filesystem `path` is on, or will be on once it is created
+pub fn free_space(path: &Path) -> Option<u64> {
+    // A relative path ends in an empty ancestor, which is the working directory
+    let existing = path
+        .ancestors()
+        .map(|p| {
+            if p.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                p
+            }
+        })
+        .find(|p| p.exists())?;
+    let path = CString::new(existing.as_os_str().as_bytes()).ok()?;
+
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: a valid nul terminated path and a struct the call fills in
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_free_space() {
+        let temp = std::env::temp_dir();
+        assert!(free_space(&temp).unwrap() > 0);
+        // Not created yet, measured on its parent
+        assert!(free_space(&temp.join("not-yet/cache")).is_some());
+        assert!(free_space(Path::new("not-yet/cache")).is_some());
+    }
+}
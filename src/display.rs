@@ -0,0 +1,504 @@
+use async_trait::async_trait;
+use image::GrayImage;
+use serde::Deserialize;
+
+use crate::config::DisplayConfig;
+use crate::device::Device;
+use crate::error::Error;
+use crate::refresh::{Rect, Update};
+
+use log::{info, warn};
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// `png` with NOT_KINDLE set, otherwise `framebuffer` on models known to support it
+    /// if the device exists, switching to `eips` if it fails, else `eips`
+    Auto,
+    Framebuffer,
+    Eips,
+    Png,
+}
+
+/*
+    Where a finished frame goes. The image is already rotated and scaled to the panel,
+    one byte of gray per pixel, and `update` says which parts of it changed.
+*/
+#[async_trait]
+pub trait Display: Send {
+    fn name(&self) -> &'static str;
+
+    async fn show(&mut self, image: &GrayImage, update: &Update) -> Result<(), Error>;
+}
+
+pub fn from_config(config: &DisplayConfig, device: &Device) -> Box<dyn Display> {
+    let display: Box<dyn Display> = match config.backend {
+        Backend::Auto if std::env::var("NOT_KINDLE").is_ok() => {
+            Box::new(PngFile::new(&config.output))
+        }
+        // Only on models whose epdc is known to take the update it sends, and even then
+        // eips takes over if it does not
+        Backend::Auto if device.framebuffer && config.device.exists() => Box::new(Fallback::new(
+            Box::new(Framebuffer::new(&config.device)),
+            Box::new(Eips::new(&config.output)),
+        )),
+        Backend::Auto | Backend::Eips => Box::new(Eips::new(&config.output)),
+        Backend::Framebuffer => Box::new(Framebuffer::new(&config.device)),
+        Backend::Png => Box::new(PngFile::new(&config.output)),
+    };
+    info!("Showing frames with {}", display.name());
+    display
+}
+
+fn save_png(image: &GrayImage, path: &Path) -> Result<(), Error> {
+    image
+        .save(path)
+        .map_err(|e| Error::Display(format!("Could not save {}: {e}", path.display())))
+}
+
+/// Just writes the frame to a png, for running on a desktop
+pub struct PngFile {
+    path: PathBuf,
+}
+
+impl PngFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        PngFile { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl Display for PngFile {
+    fn name(&self) -> &'static str {
+        "png"
+    }
+
+    async fn show(&mut self, image: &GrayImage, _update: &Update) -> Result<(), Error> {
+        save_png(image, &self.path)?;
+        info!("Saved {}", self.path.display());
+        Ok(())
+    }
+}
+
+/// Shows frames with `primary` until it fails, and from then on with `fallback`
+pub struct Fallback {
+    primary: Option<Box<dyn Display>>,
+    fallback: Box<dyn Display>,
+}
+
+impl Fallback {
+    pub fn new(primary: Box<dyn Display>, fallback: Box<dyn Display>) -> Self {
+        Fallback {
+            primary: Some(primary),
+            fallback,
+        }
+    }
+}
+
+#[async_trait]
+impl Display for Fallback {
+    fn name(&self) -> &'static str {
+        match &self.primary {
+            Some(primary) => primary.name(),
+            None => self.fallback.name(),
+        }
+    }
+
+    async fn show(&mut self, image: &GrayImage, update: &Update) -> Result<(), Error> {
+        if let Some(primary) = &mut self.primary {
+            match primary.show(image, update).await {
+                Ok(()) => return Ok(()),
+                Err(e) => warn!(
+                    "{e}, showing frames with {} from now on",
+                    self.fallback.name()
+                ),
+            }
+            self.primary = None;
+        }
+        // Whatever the primary got on screen is unknown, so all of it
+        self.fallback.show(image, &Update::Full).await
+    }
+}
+
+/// Saves a png and shells out to the kindle's `eips` to show it. eips can only redraw
+/// the whole image, so a partial update just skips the flashing.
+pub struct Eips {
+    path: PathBuf,
+}
+
+impl Eips {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Eips { path: path.into() }
+    }
+
+    async fn clear_screen() {
+        Command::new("eips")
+            .arg("-d")
+            .arg("l=0,w=9999,h=9999")
+            .output()
+            .ok();
+        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+        Command::new("eips").arg("-c").output().ok();
+        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+        Command::new("eips")
+            .arg("-d")
+            .arg("l=0,w=9999,h=9999")
+            .output()
+            .ok();
+        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+    }
+}
+
+#[async_trait]
+impl Display for Eips {
+    fn name(&self) -> &'static str {
+        "eips"
+    }
+
+    async fn show(&mut self, image: &GrayImage, update: &Update) -> Result<(), Error> {
+        save_png(image, &self.path)?;
+        if *update == Update::Full {
+            Eips::clear_screen().await;
+        }
+        let output = Command::new("eips")
+            .arg("-g")
+            .arg(&self.path)
+            .output()
+            .map_err(|e| Error::Display(format!("Could not run eips: {e}")))?;
+        if !output.status.success() {
+            return Err(Error::Display(format!(
+                "eips failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        Ok(())
+    }
+}
+
+// linux/fb.h
+const FBIOGET_VSCREENINFO: u32 = 0x4600;
+const FBIOGET_FSCREENINFO: u32 = 0x4602;
+
+#[repr(C)]
+#[derive(Default)]
+struct FbBitfield {
+    offset: u32,
+    length: u32,
+    msb_right: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct FbVarScreeninfo {
+    xres: u32,
+    yres: u32,
+    xres_virtual: u32,
+    yres_virtual: u32,
+    xoffset: u32,
+    yoffset: u32,
+    bits_per_pixel: u32,
+    grayscale: u32,
+    red: FbBitfield,
+    green: FbBitfield,
+    blue: FbBitfield,
+    transp: FbBitfield,
+    nonstd: u32,
+    activate: u32,
+    height: u32,
+    width: u32,
+    accel_flags: u32,
+    timings: [u32; 11],
+    reserved: [u32; 4],
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct FbFixScreeninfo {
+    id: [u8; 16],
+    smem_start: libc::c_ulong,
+    smem_len: u32,
+    type_: u32,
+    type_aux: u32,
+    visual: u32,
+    xpanstep: u16,
+    ypanstep: u16,
+    ywrapstep: u16,
+    line_length: u32,
+    mmio_start: libc::c_ulong,
+    mmio_len: u32,
+    accel: u32,
+    capabilities: u16,
+    reserved: [u16; 2],
+}
+
+// linux/mxcfb.h as shipped on the kindle touch and paperwhite 1, see `Device::framebuffer`
+const MXCFB_SEND_UPDATE: u32 = 0x4048_462e;
+const WAVEFORM_MODE_GC16: u32 = 2;
+const UPDATE_MODE_PARTIAL: u32 = 0;
+const UPDATE_MODE_FULL: u32 = 1;
+const TEMP_USE_AMBIENT: i32 = 0x1000;
+
+#[repr(C)]
+struct MxcfbRect {
+    top: u32,
+    left: u32,
+    width: u32,
+    height: u32,
+}
+
+#[repr(C)]
+struct MxcfbAltBufferData {
+    phys_addr: u32,
+    width: u32,
+    height: u32,
+    alt_update_region: MxcfbRect,
+}
+
+#[repr(C)]
+struct MxcfbUpdateData {
+    update_region: MxcfbRect,
+    waveform_mode: u32,
+    update_mode: u32,
+    update_marker: u32,
+    hist_bw_waveform_mode: u32,
+    hist_gray_waveform_mode: u32,
+    temp: i32,
+    flags: u32,
+    alt_buffer_data: MxcfbAltBufferData,
+}
+
+// The size is part of the ioctl number, and the var info is a fixed 160 bytes
+const _: () = assert!(std::mem::size_of::<MxcfbUpdateData>() == 0x48);
+const _: () = assert!(std::mem::size_of::<FbVarScreeninfo>() == 160);
+
+struct Geometry {
+    width: u32,
+    height: u32,
+    line_length: u32,
+    // Byte offset of the visible area, for panned framebuffers
+    offset: u64,
+}
+
+/*
+    Writes frames straight into the framebuffer and asks the epdc to refresh, so
+    there is no png on disk and no eips processes with sleeps in between.
+
+    Only 8 bit grayscale framebuffers are supported. When `device` is a regular file
+    instead of a character device the ioctls are skipped and the geometry is taken
+    from the image, which is how the tests run it.
+*/
+pub struct Framebuffer {
+    device: PathBuf,
+    update_marker: u32,
+}
+
+impl Framebuffer {
+    pub fn new(device: impl Into<PathBuf>) -> Self {
+        Framebuffer {
+            device: device.into(),
+            update_marker: 0,
+        }
+    }
+
+    fn geometry(file: &File, image: &GrayImage) -> Result<Geometry, Error> {
+        let mut var = FbVarScreeninfo::default();
+        let mut fix = FbFixScreeninfo::default();
+        // SAFETY: both structs match the kernel's layout and outlive the calls
+        unsafe {
+            if libc::ioctl(file.as_raw_fd(), FBIOGET_VSCREENINFO as _, &mut var) != 0 {
+                return Err(Error::Display(format!(
+                    "FBIOGET_VSCREENINFO failed: {}",
+                    std::io::Error::last_os_error()
+                )));
+            }
+            if libc::ioctl(file.as_raw_fd(), FBIOGET_FSCREENINFO as _, &mut fix) != 0 {
+                return Err(Error::Display(format!(
+                    "FBIOGET_FSCREENINFO failed: {}",
+                    std::io::Error::last_os_error()
+                )));
+            }
+        }
+
+        if var.bits_per_pixel != 8 {
+            return Err(Error::Display(format!(
+                "Unsupported framebuffer depth of {} bits per pixel",
+                var.bits_per_pixel
+            )));
+        }
+        if (var.xres, var.yres) != image.dimensions() {
+            warn!(
+                "Frame is {}x{} but the framebuffer is {}x{}, cropping",
+                image.width(),
+                image.height(),
+                var.xres,
+                var.yres
+            );
+        }
+        Ok(Geometry {
+            width: var.xres,
+            height: var.yres,
+            line_length: fix.line_length,
+            offset: var.yoffset as u64 * fix.line_length as u64 + var.xoffset as u64,
+        })
+    }
+
+    fn refresh(&mut self, file: &File, region: &Rect, update_mode: u32) -> Result<(), Error> {
+        // Zero means "no marker" to the driver
+        self.update_marker = self.update_marker % 1000 + 1;
+        let update = MxcfbUpdateData {
+            update_region: MxcfbRect {
+                top: region.top,
+                left: region.left,
+                width: region.width,
+                height: region.height,
+            },
+            waveform_mode: WAVEFORM_MODE_GC16,
+            update_mode,
+            update_marker: self.update_marker,
+            hist_bw_waveform_mode: 0,
+            hist_gray_waveform_mode: 0,
+            temp: TEMP_USE_AMBIENT,
+            flags: 0,
+            alt_buffer_data: MxcfbAltBufferData {
+                phys_addr: 0,
+                width: 0,
+                height: 0,
+                alt_update_region: MxcfbRect {
+                    top: 0,
+                    left: 0,
+                    width: 0,
+                    height: 0,
+                },
+            },
+        };
+        // SAFETY: the struct matches the kindle kernel's layout and outlives the call
+        if unsafe { libc::ioctl(file.as_raw_fd(), MXCFB_SEND_UPDATE as _, &update) } != 0 {
+            return Err(Error::Display(format!(
+                "MXCFB_SEND_UPDATE failed: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Display for Framebuffer {
+    fn name(&self) -> &'static str {
+        "framebuffer"
+    }
+
+    async fn show(&mut self, image: &GrayImage, update: &Update) -> Result<(), Error> {
+        let now = Instant::now();
+        let device = self.device.display().to_string();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.device)
+            .map_err(|e| Error::Display(format!("Could not open {device}: {e}")))?;
+        let is_device = file
+            .metadata()
+            .map_err(|e| Error::Display(format!("Could not stat {device}: {e}")))?
+            .file_type()
+            .is_char_device();
+
+        let geometry = if is_device {
+            Framebuffer::geometry(&file, image)?
+        } else {
+            Geometry {
+                width: image.width(),
+                height: image.height(),
+                line_length: image.width(),
+                offset: 0,
+            }
+        };
+
+        let width = geometry.width.min(image.width()) as usize;
+        let mut buffer = vec![0xff; (geometry.line_length * geometry.height) as usize];
+        for (y, row) in image
+            .as_raw()
+            .chunks_exact(image.width() as usize)
+            .take(geometry.height as usize)
+            .enumerate()
+        {
+            let start = y * geometry.line_length as usize;
+            buffer[start..start + width].copy_from_slice(&row[..width]);
+        }
+
+        file.seek(SeekFrom::Start(geometry.offset))
+            .and_then(|_| file.write_all(&buffer))
+            .map_err(|e| Error::Display(format!("Could not write to {device}: {e}")))?;
+
+        if is_device {
+            let screen = Rect {
+                left: 0,
+                top: 0,
+                width: geometry.width,
+                height: geometry.height,
+            };
+            match update {
+                Update::Full => self.refresh(&file, &screen, UPDATE_MODE_FULL)?,
+                Update::Partial(rects) => {
+                    for rect in rects {
+                        let rect = Rect {
+                            width: rect.width.min(screen.width.saturating_sub(rect.left)),
+                            height: rect.height.min(screen.height.saturating_sub(rect.top)),
+                            ..*rect
+                        };
+                        if rect.width > 0 && rect.height > 0 {
+                            self.refresh(&file, &rect, UPDATE_MODE_PARTIAL)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        let elapsed = format!("{:.2?}", now.elapsed());
+        info!("Wrote the frame to {device} in {elapsed}");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_framebuffer_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("fb0");
+        std::fs::write(&path, []).unwrap();
+
+        let image = GrayImage::from_fn(6, 4, |x, y| image::Luma([(x * 40 + y) as u8]));
+        let mut display = Framebuffer::new(&path);
+        display.show(&image, &Update::Full).await.unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), image.as_raw().as_slice());
+    }
+
+    #[tokio::test]
+    async fn test_fallback() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("output.png");
+        let mut display = Fallback::new(
+            Box::new(Framebuffer::new("/no-such-dir/fb0")),
+            Box::new(PngFile::new(&path)),
+        );
+        assert_eq!(display.name(), "framebuffer");
+
+        let image = GrayImage::from_pixel(6, 4, image::Luma([0x80]));
+        display
+            .show(&image, &Update::Partial(vec![]))
+            .await
+            .unwrap();
+        assert_eq!(display.name(), "png");
+        assert!(path.exists());
+    }
+}
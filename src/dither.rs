@@ -0,0 +1,162 @@
+use image::GrayImage;
+use serde::Deserialize;
+
+use crate::refresh::Rect;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dither {
+    /// Snap every pixel to the nearest level
+    None,
+    /// Error diffusion, smoothest gradients
+    FloydSteinberg,
+    /// Ordered 4x4 pattern, no crawling noise between refreshes of a similar image
+    Bayer,
+}
+
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+fn nearest(value: f32, levels: u8) -> u8 {
+    let step = 255.0 / (levels - 1) as f32;
+    ((value / step).round() * step).clamp(0.0, 255.0) as u8
+}
+
+/*
+    Brings a frame down to the panel's gray levels. Text and line art are only snapped
+    to the nearest level, which keeps their edges crisp, while `photos` (the radar map,
+    any other bitmaps) are dithered so their gradients do not band.
+*/
+pub fn quantize(image: &mut GrayImage, levels: u8, photos: &[Rect], dither: Dither) {
+    let original = image.clone();
+    for pixel in image.pixels_mut() {
+        pixel.0[0] = nearest(pixel.0[0] as f32, levels);
+    }
+    for photo in photos {
+        dither_region(image, &original, clamp(photo, image), levels, dither);
+    }
+}
+
+fn clamp(rect: &Rect, image: &GrayImage) -> Rect {
+    let left = rect.left.min(image.width());
+    let top = rect.top.min(image.height());
+    Rect {
+        left,
+        top,
+        width: rect.width.min(image.width() - left),
+        height: rect.height.min(image.height() - top),
+    }
+}
+
+fn dither_region(
+    image: &mut GrayImage,
+    original: &GrayImage,
+    region: Rect,
+    levels: u8,
+    dither: Dither,
+) {
+    let (width, height) = (region.width as usize, region.height as usize);
+    let step = 255.0 / (levels - 1) as f32;
+
+    // Working copy of the region, with room for diffused error
+    let mut values: Vec<f32> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            original
+                .get_pixel(region.left + x as u32, region.top + y as u32)
+                .0[0] as f32
+        })
+        .collect();
+
+    for y in 0..height {
+        for x in 0..width {
+            let value = values[y * width + x];
+            let quantized = match dither {
+                Dither::None => nearest(value, levels),
+                Dither::Bayer => {
+                    let threshold = (BAYER[y % 4][x % 4] as f32 + 0.5) / 16.0 - 0.5;
+                    nearest(value + threshold * step, levels)
+                }
+                Dither::FloydSteinberg => {
+                    let quantized = nearest(value, levels);
+                    let error = value - quantized as f32;
+                    let mut spread = |dx: isize, dy: usize, weight: f32| {
+                        let nx = x as isize + dx;
+                        if nx >= 0 && (nx as usize) < width && y + dy < height {
+                            values[(y + dy) * width + nx as usize] += error * weight;
+                        }
+                    };
+                    spread(1, 0, 7.0 / 16.0);
+                    spread(-1, 1, 3.0 / 16.0);
+                    spread(0, 1, 5.0 / 16.0);
+                    spread(1, 1, 1.0 / 16.0);
+                    quantized
+                }
+            };
+            image.put_pixel(
+                region.left + x as u32,
+                region.top + y as u32,
+                image::Luma([quantized]),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient() -> GrayImage {
+        GrayImage::from_fn(64, 8, |x, _| image::Luma([(x * 4) as u8]))
+    }
+
+    fn mean(image: &GrayImage) -> f32 {
+        image.pixels().map(|p| p.0[0] as f32).sum::<f32>() / (image.width() * image.height()) as f32
+    }
+
+    #[test]
+    fn test_quantize_levels() {
+        for (levels, allowed) in [
+            (4, vec![0, 85, 170, 255]),
+            (16, (0..16).map(|i| i * 17).collect()),
+        ] {
+            for dither in [Dither::None, Dither::FloydSteinberg, Dither::Bayer] {
+                let mut image = gradient();
+                let whole = Rect {
+                    left: 0,
+                    top: 0,
+                    width: 64,
+                    height: 8,
+                };
+                quantize(&mut image, levels, &[whole], dither);
+                assert!(
+                    image.pixels().all(|p| allowed.contains(&p.0[0])),
+                    "{dither:?} with {levels} levels"
+                );
+                // Dithering keeps the overall tone of the gradient
+                assert!(
+                    (mean(&image) - mean(&gradient())).abs() < 12.0,
+                    "{dither:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_only_photos_are_dithered() {
+        let mut image = GrayImage::from_pixel(16, 16, image::Luma([100]));
+        let photo = Rect {
+            left: 8,
+            top: 0,
+            width: 100,
+            height: 16,
+        };
+        quantize(&mut image, 4, &[photo], Dither::FloydSteinberg);
+
+        // Outside the photo a flat gray snaps to one level, inside it is a mix
+        let (outside, inside): (Vec<_>, Vec<_>) =
+            image.enumerate_pixels().partition(|(x, _, _)| *x < 8);
+        assert!(outside.iter().all(|(_, _, p)| p.0[0] == 85));
+        assert!(inside.iter().any(|(_, _, p)| p.0[0] == 85));
+        assert!(inside.iter().any(|(_, _, p)| p.0[0] == 170));
+    }
+}
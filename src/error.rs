@@ -0,0 +1,109 @@
+use thiserror::Error;
+
+/*
+    Everything that can go wrong, sorted by what the main loop does about it: network
+    and server errors are retried, parse errors mean an api changed and only the cache
+    helps, config errors stop the program, render and display errors are shown on screen.
+*/
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Could not reach the server at all, or it took too long to answer
+    #[error("network error: {0}")]
+    Network(String),
+    /// The server answered, with an error status
+    #[error("{url} answered {status}")]
+    Status { url: String, status: u16 },
+    /// Got an answer, but not one we understand
+    #[error("could not parse {0}")]
+    Parse(String),
+    /// Missing or invalid settings, or files like the api keys
+    #[error("config error: {0}")]
+    Config(String),
+    /// Reading or writing local files
+    #[error("{0}")]
+    Io(String),
+    #[error("could not render: {0}")]
+    Render(String),
+    #[error("could not show the frame: {0}")]
+    Display(String),
+}
+
+impl Error {
+    /// Whether trying again in a little while may work
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Network(_) => true,
+            Error::Status { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// A few words for the banner on the dashboard, the log has the details
+    pub fn summary(&self) -> String {
+        match self {
+            Error::Network(_) => "no connection".to_string(),
+            Error::Status { status, .. } => format!("server error {status}"),
+            Error::Parse(_) => "unexpected data".to_string(),
+            Error::Config(_) => "not set up".to_string(),
+            Error::Io(_) => "could not read it".to_string(),
+            Error::Render(_) => "could not render".to_string(),
+            Error::Display(_) => "could not show".to_string(),
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        if let Some(status) = e.status() {
+            // Without the query, which may hold an api key
+            let url = e
+                .url()
+                .map(|url| format!("{}{}", url.host_str().unwrap_or_default(), url.path()))
+                .unwrap_or_default();
+            Error::Status {
+                url,
+                status: status.as_u16(),
+            }
+        } else if e.is_decode() {
+            Error::Parse(format!("the response: {}", e.without_url()))
+        } else {
+            Error::Network(e.without_url().to_string())
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Parse(format!("json: {e}"))
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(e: image::ImageError) -> Self {
+        Error::Parse(format!("image: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(Error::Network("connection refused".to_string()).is_retryable());
+        let status = |status| Error::Status {
+            url: "api.example.com/forecast".to_string(),
+            status,
+        };
+        assert!(status(503).is_retryable());
+        assert!(status(429).is_retryable());
+        assert!(!status(401).is_retryable());
+        assert!(!Error::Parse("json: expected value".to_string()).is_retryable());
+    }
+}
@@ -0,0 +1,193 @@
+use base64::prelude::*;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::http::{redact, Body};
+
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+    Http responses saved to a directory with `--record`, and served back from it with
+    `--replay` instead of going online, to work on the dashboard without a connection
+    and to test against real answers.
+
+    One json file per url, named after its host and path. The query is left out, it
+    holds api keys and dates, so a replayed url matches whatever was recorded for the
+    same path. Urls with the date in the path, like the AEMET map, fall back to the
+    fixture of the same host that shares the longest start with it.
+*/
+#[derive(Clone, Debug)]
+pub enum Fixtures {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Fixture {
+    /// Host and path, see `http::redact`
+    url: String,
+    status: u16,
+    /// Whether `body` is base64, for images and anything else that is not text
+    base64: bool,
+    body: String,
+}
+
+impl Fixture {
+    fn body(&self) -> Result<Body, Error> {
+        let bytes = if self.base64 {
+            BASE64_STANDARD
+                .decode(&self.body)
+                .map_err(|e| Error::Parse(format!("fixture for {}: {e}", self.url)))?
+        } else {
+            self.body.clone().into_bytes()
+        };
+        Ok(Body::from(bytes))
+    }
+}
+
+impl Fixtures {
+    /// Saves the answer to `url`, if there was one
+    pub fn record(&self, url: &Url, result: &Result<Body, Error>) {
+        let Fixtures::Record(dir) = self else {
+            return;
+        };
+        let url = redact(url);
+        let (status, bytes) = match result {
+            Ok(body) => (200, body.bytes()),
+            Err(Error::Status { status, .. }) => (*status, &[][..]),
+            // Never got an answer
+            Err(_) => return,
+        };
+        let (base64, body) = match std::str::from_utf8(bytes) {
+            Ok(text) => (false, text.to_string()),
+            Err(_) => (true, BASE64_STANDARD.encode(bytes)),
+        };
+        let fixture = Fixture {
+            url: url.clone(),
+            status,
+            base64,
+            body,
+        };
+
+        let path = dir.join(file_name(&url));
+        let written = fs::create_dir_all(dir)
+            .and_then(|_| Ok(serde_json::to_vec_pretty(&fixture)?))
+            .and_then(|contents| fs::write(&path, contents));
+        match written {
+            Ok(()) => info!("Recorded {url} to {}", path.display()),
+            Err(e) => warn!("Could not record {url} to {}: {e}", path.display()),
+        }
+    }
+
+    /// The recorded answer to `url`, or the one for the closest url of the same host
+    pub fn replay(&self, url: &Url) -> Result<Body, Error> {
+        let Fixtures::Replay(dir) = self else {
+            return Err(Error::Io("not replaying".to_string()));
+        };
+        let url = redact(url);
+        let fixtures = load(dir);
+        let fixture = closest(&fixtures, &url)
+            .ok_or_else(|| Error::Io(format!("nothing recorded for {url}")))?;
+
+        if fixture.url == url {
+            info!("Replaying {url}");
+        } else {
+            warn!("Replaying {} for {url}", fixture.url);
+        }
+        match fixture.status {
+            200 => fixture.body(),
+            status => Err(Error::Status { url, status }),
+        }
+    }
+}
+
+fn file_name(url: &str) -> String {
+    let name: String = url
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{}.json", name.trim_matches('_'))
+}
+
+fn load(dir: &Path) -> Vec<Fixture> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        warn!("No fixtures in {}", dir.display());
+        return vec![];
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|e| e == "json"))
+        .filter_map(|path| {
+            let contents = fs::read(&path).ok()?;
+            match serde_json::from_slice(&contents) {
+                Ok(fixture) => Some(fixture),
+                Err(e) => {
+                    warn!("Ignoring unreadable fixture {}: {e}", path.display());
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+// The fixture for `url`, or the one of the same host that shares the longest start
+fn closest<'a>(fixtures: &'a [Fixture], url: &str) -> Option<&'a Fixture> {
+    let host = |url: &str| url.split('/').next().unwrap_or_default().to_string();
+    let shared = |fixture: &Fixture| {
+        fixture
+            .url
+            .chars()
+            .zip(url.chars())
+            .take_while(|(a, b)| a == b)
+            .count()
+    };
+    fixtures
+        .iter()
+        .filter(|fixture| host(&fixture.url) == host(url))
+        .max_by_key(|fixture| shared(fixture))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_replay() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let record = Fixtures::Record(dir.to_path_buf());
+        let replay = Fixtures::Replay(dir.to_path_buf());
+        let url = |url: &str| Url::parse(url).unwrap();
+
+        let forecast = url("http://api.example.com/forecast?key=secret");
+        record.record(&forecast, &Ok(Body::from(b"{}".to_vec())));
+        let map = url("https://maps.example.com/2024/01/01/map.png");
+        record.record(&map, &Ok(Body::from(vec![0x89, b'P', b'N', b'G', 0xff])));
+        let gone = url("http://api.example.com/gone");
+        let not_found = Error::Status {
+            url: "api.example.com/gone".to_string(),
+            status: 404,
+        };
+        record.record(&gone, &Err(not_found));
+
+        // The key stays out of the fixtures
+        let written = fs::read_to_string(dir.join("api_example_com_forecast.json")).unwrap();
+        assert!(!written.contains("secret"));
+
+        let body = replay
+            .replay(&url("http://api.example.com/forecast?key=other"))
+            .unwrap();
+        assert_eq!(body.bytes(), b"{}");
+        let body = replay
+            .replay(&url("https://maps.example.com/2024/01/02/map.png"))
+            .unwrap();
+        assert_eq!(body.bytes(), [0x89, b'P', b'N', b'G', 0xff]);
+        let e = replay.replay(&gone).err().unwrap();
+        assert!(matches!(e, Error::Status { status: 404, .. }));
+        assert!(replay.replay(&url("http://unknown.example.com/")).is_err());
+    }
+}
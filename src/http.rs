@@ -0,0 +1,366 @@
+use base64::prelude::*;
+use chrono::Utc;
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::{Client, IntoUrl, Request, RequestBuilder, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::Cache;
+use crate::config::HttpConfig;
+use crate::error::Error;
+use crate::fixtures::Fixtures;
+
+use log::{info, warn};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Kept bodies not downloaded or confirmed by a 304 since, like the map of a day long
+// gone, are removed after this
+const KEEP_BODIES: Duration = Duration::from_secs(2 * 24 * 60 * 60);
+
+pub const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/*
+    The one http client every source fetches through, so they all get the same timeout,
+    user agent and proxy. `send` retries network errors and overloaded servers with an
+    exponential backoff, and logs every request with how long it took.
+
+    Bodies that come with an ETag or Last-Modified are kept in `<cache dir>/http`, the
+    next request for the same url asks whether it changed and reuses the kept body on a
+    304. The AEMET map and the tide table rarely change, and the Wi-Fi is slow.
+
+    With fixtures, responses are also recorded, or replayed without going online.
+
+    Cheap to clone, clones share the connection pool.
+*/
+#[derive(Clone)]
+pub struct Http {
+    client: Client,
+    retries: u32,
+    backoff: Duration,
+    conditional: Cache,
+    fixtures: Option<Fixtures>,
+}
+
+/// What a conditional request needs to know about the body kept for a url
+#[derive(Serialize, Deserialize)]
+struct Validated {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// base64
+    body: String,
+}
+
+/// A whole response body, fresh or the kept one when the server said it did not change
+pub struct Body {
+    bytes: Vec<u8>,
+}
+
+impl Body {
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        Ok(serde_json::from_slice(&self.bytes)?)
+    }
+
+    pub fn text(&self) -> Result<String, Error> {
+        String::from_utf8(self.bytes.clone())
+            .map_err(|e| Error::Parse(format!("the response: {e}")))
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Body { bytes }
+    }
+}
+
+impl Http {
+    pub fn from_config(config: &HttpConfig, cache_dir: &Path) -> Result<Self, Error> {
+        let mut builder = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .user_agent(&config.user_agent);
+        if let Some(proxy) = &config.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| Error::Config(format!("Invalid http.proxy {proxy}: {e}")))?;
+            builder = builder.proxy(proxy);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| Error::Config(format!("Could not build the http client: {e}")))?;
+
+        Ok(Http {
+            client,
+            retries: config.retries,
+            backoff: Duration::from_secs(config.backoff_seconds),
+            conditional: Cache::new(cache_dir.join("http")),
+            fixtures: None,
+        })
+    }
+
+    pub fn with_fixtures(self, fixtures: Fixtures) -> Self {
+        Http {
+            fixtures: Some(fixtures),
+            ..self
+        }
+    }
+
+    /// Whether responses come from recorded fixtures, without going online
+    pub fn replaying(&self) -> bool {
+        matches!(self.fixtures, Some(Fixtures::Replay(_)))
+    }
+
+    pub fn get(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    /// Sends `request` and reads the body, asking first whether the one kept for the url
+    /// is still current. Retries what may work a little later, error statuses are errors.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Body, Error> {
+        let request = request.build()?;
+        match &self.fixtures {
+            Some(fixtures @ Fixtures::Replay(_)) => fixtures.replay(request.url()),
+            Some(fixtures @ Fixtures::Record(_)) => {
+                let url = request.url().clone();
+                let result = self.send_conditional(request).await;
+                fixtures.record(&url, &result);
+                result
+            }
+            None => self.send_conditional(request).await,
+        }
+    }
+
+    async fn send_conditional(&self, mut request: Request) -> Result<Body, Error> {
+        let url = redact(request.url());
+        let key = cache_key(request.url());
+
+        let kept = self
+            .conditional
+            .load::<Validated>(&key)
+            .and_then(|(_, kept)| Some((BASE64_STANDARD.decode(&kept.body).ok()?, kept)));
+        if let Some((_, kept)) = &kept {
+            add_validators(request.headers_mut(), kept);
+        }
+
+        let was_kept = kept.is_some();
+        let response = self.execute(request).await?;
+        match kept {
+            Some((bytes, _)) if response.status() == StatusCode::NOT_MODIFIED => {
+                info!("Cache hit for {url}, {} bytes not downloaded", bytes.len());
+                // Still current, however long ago it was downloaded
+                if let Err(e) = self.conditional.touch(&key) {
+                    warn!("{e}");
+                }
+                return Ok(Body { bytes });
+            }
+            Some(_) => info!("Cache miss for {url}, it changed"),
+            None => info!("Cache miss for {url}, nothing kept"),
+        }
+
+        let validated = validators(response.headers());
+        let bytes = response.bytes().await?.to_vec();
+        if let Some((etag, last_modified)) = validated {
+            let kept = Validated {
+                etag,
+                last_modified,
+                body: BASE64_STANDARD.encode(&bytes),
+            };
+            if let Err(e) = self.conditional.store(&key, Utc::now(), &kept) {
+                warn!("Could not keep the body of {url}: {e}");
+            }
+            self.conditional.remove_older_than(KEEP_BODIES);
+        } else if was_kept {
+            // It can't be validated any more, so it would only ever be sent along stale
+            if let Err(e) = self.conditional.remove(&key) {
+                warn!("{e}");
+            }
+        }
+        Ok(Body { bytes })
+    }
+
+    async fn execute(&self, request: Request) -> Result<Response, Error> {
+        let url = redact(request.url());
+
+        let mut attempt = 0;
+        loop {
+            let now = Instant::now();
+            // Only bodiless requests are sent, those can always be cloned
+            let attempt_request = request
+                .try_clone()
+                .ok_or_else(|| Error::Network(format!("{url} has a streaming body")))?;
+            let result = match self.client.execute(attempt_request).await {
+                Ok(response) => response.error_for_status().map_err(Error::from),
+                Err(e) => Err(Error::from(e)),
+            };
+
+            let elapsed = format!("{:.2?}", now.elapsed());
+            match result {
+                Ok(response) => {
+                    info!("GET {url} {} in {elapsed}", response.status());
+                    return Ok(response);
+                }
+                Err(e) if e.is_retryable() && attempt < self.retries => {
+                    let delay = self.backoff * 2u32.pow(attempt);
+                    warn!("GET {url} failed in {elapsed}: {e}, retrying in {delay:?}");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    warn!("GET {url} failed in {elapsed}: {e}");
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+/// For logs and fixtures, without the query which may hold an api key
+pub fn redact(url: &Url) -> String {
+    format!("{}{}", url.host_str().unwrap_or_default(), url.path())
+}
+
+// A file name for the url, the query included but not readable. A new rust version may
+// hash differently, which only costs one full download per url
+fn cache_key(url: &Url) -> String {
+    let mut hasher = DefaultHasher::new();
+    url.as_str().hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+fn add_validators(headers: &mut HeaderMap, kept: &Validated) {
+    let mut add = |name, value: &Option<String>| {
+        if let Some(value) = value.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+            headers.insert(name, value);
+        }
+    };
+    add(header::IF_NONE_MATCH, &kept.etag);
+    add(header::IF_MODIFIED_SINCE, &kept.last_modified);
+}
+
+// The ETag and Last-Modified of a response, if it has any
+fn validators(headers: &HeaderMap) -> Option<(Option<String>, Option<String>)> {
+    let value = |name| Some(headers.get(name)?.to_str().ok()?.to_string());
+    let etag = value(header::ETAG);
+    let last_modified = value(header::LAST_MODIFIED);
+    (etag.is_some() || last_modified.is_some()).then_some((etag, last_modified))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{channel, Receiver};
+
+    // Answers one connection per response, in order. Returns the url and the requests
+    fn serve(responses: &'static [(u16, &'static str)]) -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (sender, requests) = channel();
+        std::thread::spawn(move || {
+            for (status, headers) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 1024];
+                let read = stream.read(&mut request).unwrap();
+                // Nobody may be listening for them
+                let request = String::from_utf8_lossy(&request[..read]).to_lowercase();
+                let _ = sender.send(request);
+                let response = format!(
+                    "HTTP/1.1 {status} X\r\n{headers}Content-Length: 2\r\nConnection: close\r\n\r\nok"
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (format!("http://{address}/forecast?key=secret"), requests)
+    }
+
+    fn http(retries: u32, cache_dir: &Path) -> Http {
+        Http::from_config(
+            &HttpConfig {
+                retries,
+                backoff_seconds: 0,
+                ..Default::default()
+            },
+            cache_dir,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_retries() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+
+        // Overloaded twice, then fine
+        let (url, _) = serve(&[(503, ""), (503, ""), (200, "")]);
+        let http = self::http(2, dir);
+        let body = http.send(http.get(&url)).await.unwrap();
+        assert_eq!(body.text().unwrap(), "ok");
+
+        // Out of retries
+        let (url, _) = serve(&[(503, ""), (503, "")]);
+        let http = self::http(1, dir);
+        let e = http.send(http.get(&url)).await.err().unwrap();
+        assert!(matches!(e, Error::Status { status: 503, .. }));
+
+        // Not worth retrying, the second answer would have been fine
+        let (url, _) = serve(&[(404, ""), (200, "")]);
+        let http = self::http(2, dir);
+        let e = http.send(http.get(&url)).await.err().unwrap();
+        assert!(matches!(e, Error::Status { status: 404, ref url } if !url.contains("secret")));
+    }
+
+    #[tokio::test]
+    async fn test_conditional() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let http = http(0, dir);
+        let (url, requests) = serve(&[
+            (
+                200,
+                "ETag: \"v1\"\r\nLast-Modified: Wed, 21 Oct 2015 07:28:00 GMT\r\n",
+            ),
+            (304, "ETag: \"v1\"\r\n"),
+            (304, "ETag: \"v1\"\r\n"),
+            (200, ""),
+        ]);
+
+        let body = http.send(http.get(&url)).await.unwrap();
+        assert_eq!(body.bytes(), b"ok");
+        assert!(!requests.recv().unwrap().contains("if-none-match"));
+
+        // The 304 has no body, the kept one is served
+        let body = http.send(http.get(&url)).await.unwrap();
+        assert_eq!(body.bytes(), b"ok");
+        let request = requests.recv().unwrap();
+        assert!(request.contains("if-none-match: \"v1\""));
+        assert!(request.contains("if-modified-since: wed, 21 oct 2015 07:28:00 gmt"));
+
+        // Downloaded long ago but still confirmed, so it is kept
+        let kept = dir
+            .join("http")
+            .join(format!("{}.json", cache_key(&Url::parse(&url).unwrap())));
+        let long_ago = std::time::SystemTime::now() - KEEP_BODIES * 2;
+        std::fs::File::options()
+            .append(true)
+            .open(&kept)
+            .unwrap()
+            .set_modified(long_ago)
+            .unwrap();
+        let body = http.send(http.get(&url)).await.unwrap();
+        assert_eq!(body.bytes(), b"ok");
+        http.conditional.remove_older_than(KEEP_BODIES);
+        assert!(kept.exists());
+        requests.recv().unwrap();
+
+        // Answered without validators this time, nothing to send along any more
+        let body = http.send(http.get(&url)).await.unwrap();
+        assert_eq!(body.bytes(), b"ok");
+        assert!(requests.recv().unwrap().contains("if-none-match: \"v1\""));
+        assert!(!kept.exists());
+    }
+}
@@ -1,77 +1,248 @@
 // RUSTFLAGS="-C target-feature=+crt-static" cross build --target arm-unknown-linux-musleabi --release
 
-// mod calendar;
-mod weather;
-// mod news;
+mod assets;
+mod battery;
+mod cache;
+mod chart;
+mod config;
+mod device;
+mod disk;
+mod display;
+mod dither;
+mod error;
+mod fixtures;
+mod http;
+mod network;
+mod pages;
+mod power;
 mod radar;
+mod refresh;
 mod renderer;
+mod secrets;
+mod source;
 mod stats;
-
+mod template;
 mod utils;
+mod weather;
 
 use chrono::Timelike;
-use env_logger;
-use futures::FutureExt;
-use log;
-use std::{env, panic::AssertUnwindSafe, time::Duration};
+use clap::Parser;
+use std::{
+    env,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 
-use log::info;
+use config::Config;
+use device::Device;
+use display::Display;
+use error::Error;
+use fixtures::Fixtures;
+use log::{error, info, warn};
+use network::Network;
+use pages::Rotation;
+use power::Power;
+use refresh::Refresher;
+use secrets::Secrets;
+use source::{KindleDisplayData, Registry};
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Standalone kindle weather dashboard")]
+struct Args {
+    /// Path to the TOML config file
+    #[arg(long, env = "KINDLE_BUENO_CONFIG", default_value = config::DEFAULT_CONFIG_PATH)]
+    config: PathBuf,
+    /// Save every http response the sources get to this directory
+    #[arg(long, value_name = "DIR", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Serve the http responses saved with --record instead of going online
+    #[arg(long, value_name = "DIR")]
+    replay: Option<PathBuf>,
+    /// Show one frame and exit, with an error status if it failed
+    #[arg(long)]
+    once: bool,
+    /// Save the frame here instead, or with a .svg extension the filled in template too
+    #[arg(long, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// Only save the frame, never show it on the screen
+    #[arg(long)]
+    no_display: bool,
+    /// Fetch one source, print what it got as json and exit
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["once", "check"])]
+    source: Option<String>,
+    /// Check the config and that every enabled source is set up, then exit
+    #[arg(long, conflicts_with = "once")]
+    check: bool,
+}
 
-const SLEEP_MINUTES: u32 = 60;
+// Sources that failed in a way that may not last get another try after this
+const RETRY_DELAY: Duration = Duration::from_secs(30);
 
-fn get_duration_until_next_interval() -> u64 {
+// Panels without REAGL ghost sooner, they get a full refresh at least this often
+const FULL_EVERY_WITHOUT_REAGL: u32 = 4;
+
+// A wake-up can come a little early, that still counts as time to fetch
+const FETCH_SLACK: Duration = Duration::from_secs(60);
+
+fn get_duration_until_next_interval(sleep_minutes: u32) -> u64 {
     let now = chrono::Local::now();
     let minutes = now.minute();
     let seconds = now.second();
-    let next_interval_minutes = SLEEP_MINUTES - (minutes % SLEEP_MINUTES);
+    let next_interval_minutes = sleep_minutes - (minutes % sleep_minutes);
     (next_interval_minutes * 60 - seconds) as u64
 }
 
-async fn panic_wrapper() -> Result<(), String> {
-    /*
+struct Dashboard {
+    /// Last fetch, shared by every page until the next refresh
+    data: Option<KindleDisplayData>,
+    /// When the last fetch that got online ran. Wall clock time, which unlike an `Instant`
+    /// keeps counting while suspended
+    fetched_at: Option<SystemTime>,
+    rotation: Rotation,
+    device: Device,
+    refresher: Refresher,
+}
+
+impl Dashboard {
+    fn new(config: &Config, device: Device) -> Self {
+        // Without partial refreshes, every update is a full one
+        let full_every = if !device.partial_refresh {
+            1
+        } else if device.reagl {
+            config.display.full_refresh_every
+        } else {
+            config
+                .display
+                .full_refresh_every
+                .min(FULL_EVERY_WITHOUT_REAGL)
+        };
+        Dashboard {
+            data: None,
+            fetched_at: None,
+            rotation: Rotation::default(),
+            device,
+            refresher: Refresher::new(full_every),
+        }
+    }
 
-       The only time a panic should happen is if we cannot allocate memory, write to disk, or create a valid svg.
-       This SHOULD require user attention, as most likely the kindle has run out of space.
+    /// Whether the data is `sleep_minutes` old, however late this wake-up came
+    fn fetch_due(&self, sleep_minutes: u32) -> bool {
+        let Some(fetched_at) = self.fetched_at else {
+            return true;
+        };
+        // A clock that went back does not get to postpone it
+        fetched_at.elapsed().map_or(true, |age| {
+            age + FETCH_SLACK >= Duration::from_secs(sleep_minutes as u64 * 60)
+        })
+    }
+}
 
-       In the case that eips can not even be used to show the panic message, only then does
-       the code "promote" the panic and stop the program permanently.
-    */
+/// Fetches every source, retrying once the ones that failed in a way that may not last
+async fn fetch(registry: &Registry, network: &Network) -> KindleDisplayData {
+    let online = network.connect().await;
+    let mut data = registry.fetch_all().await;
 
-    let may_panic = async {
-        utils::check_internet_with_retries(3, Duration::from_secs(5))
-            .await
-            .unwrap();
-        renderer::render_png().await
-    };
+    if online && data.errors().any(|(_, e)| e.is_retryable()) {
+        warn!("Retrying failed sources in {RETRY_DELAY:?}");
+        tokio::time::sleep(RETRY_DELAY).await;
+        // Only the failed ones are due, the rest are reused
+        data = registry.fetch_all().await;
+    }
 
-    let panic_result = AssertUnwindSafe(may_panic).catch_unwind().await;
+    // Whatever failed is shown from the cache, or as missing
+    for (name, e) in data.errors() {
+        match e {
+            Error::Config(_) => error!("{name} will keep failing until it is set up: {e}"),
+            Error::Parse(_) => error!("{name} sent something unexpected, did its api change? {e}"),
+            _ => {}
+        }
+    }
 
-    match panic_result {
-        Ok(_r) => return Ok(()),
-        Err(e) => {
-            let &panic_message;
-            if let Some(s) = e.downcast_ref::<String>() {
-                panic_message = s.to_string();
-            } else if let Some(s) = e.downcast_ref::<&str>() {
-                panic_message = s.to_string();
-            } else {
-                panic_message = "Panic occurred but could not be downcast to a string".to_string();
+    data.offline = !online;
+    data
+}
+
+/// Shows the next page, fetching first if it is time to
+async fn update(
+    config: &Config,
+    registry: &Registry,
+    network: &Network,
+    dashboard: &mut Dashboard,
+    display: &mut dyn Display,
+    refresh: bool,
+) -> Result<(), Error> {
+    let data = match dashboard.data.take() {
+        Some(data) if !refresh => data,
+        _ => {
+            let data = fetch(registry, network).await;
+            // Offline, the next wake-up tries again
+            if !data.offline {
+                dashboard.fetched_at = Some(SystemTime::now());
             }
+            data
+        }
+    };
 
-            // Minimal render to show panic message incase it an svg based fail
-            let r = renderer::show_panic(&panic_message).await;
+    // Taken after fetching, which we assume takes less than a minute, so the clock on the
+    // dashboard shows the nice "every 15 minutes" time
+    let now = chrono::Utc::now().with_timezone(&config.timezone);
+    let page = dashboard.rotation.next(&config.pages, now.time());
+    let result = renderer::render_png(
+        config,
+        &dashboard.device,
+        &data,
+        page,
+        &now,
+        display,
+        &mut dashboard.refresher,
+    )
+    .await;
 
-            match r {
-                // We showed the panic message successfully, but we still panicked...
-                Ok(_r) => Err(format!("Showed panic message \"{panic_message}\"")),
+    dashboard.data = Some(data);
+    result
+}
 
-                // We cant even show the panic message. Now we REALLY panic.
-                Err(e) => panic!("Could not show panic message: \"{panic_message}\", due to: {e}"),
+async fn handle(result: Result<(), Error>, show_errors: bool) {
+    match result {
+        Ok(()) => {}
+        // No dashboard to show, most likely a broken template or out of memory. This
+        // needs someone to look at the kindle, so it goes straight on the screen
+        Err(e @ Error::Render(_)) => {
+            error!("{e}");
+            if !show_errors {
+                return;
+            }
+            if let Err(shown) = renderer::show_error(&e).await {
+                error!("Could not show the error either: {shown}");
             }
         }
+        // Could not show it, the refresher forgot what is on screen so the next update
+        // redraws all of it
+        Err(e) => error!("{e}, trying again on the next update"),
     }
 }
 
+/// Prints whether the config and every source are good to go, for `--check`
+fn check(registry: &Registry, eips: bool) -> bool {
+    println!("config: ok");
+    let mut checks = registry.check();
+    if eips {
+        checks.push(("eips", utils::check_eips()));
+    }
+
+    let mut ok = true;
+    for (name, result) in checks {
+        match result {
+            Ok(()) => println!("{name}: ok"),
+            Err(e) => {
+                println!("{name}: {e}");
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
 #[tokio::main]
 async fn main() {
     if env::var("RUST_LOG").is_err() {
@@ -79,19 +250,167 @@ async fn main() {
     }
     env_logger::init();
 
-    if env::var("NOT_KINDLE").is_err() {
-        utils::check_xrandr().unwrap();
-        utils::check_eips().unwrap();
-        utils::check_sensitives().unwrap();
+    let args = Args::parse();
+    let mut config = match Config::load(&args.config) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    if args.no_display {
+        config.display.backend = display::Backend::Png;
+    }
+    match &args.output {
+        Some(path) if path.extension().is_some_and(|e| e == "svg") => {
+            config.display.svg = Some(path.clone())
+        }
+        Some(path) => config.display.output = path.clone(),
+        None => {}
+    }
+    if let Some(name) = &args.source {
+        // Whether it is enabled or not
+        config.sources = vec![name.clone()];
+    }
+
+    let fixtures = match (&args.record, &args.replay) {
+        (Some(dir), _) => Some(Fixtures::Record(dir.clone())),
+        (_, Some(dir)) => Some(Fixtures::Replay(dir.clone())),
+        _ => None,
+    };
+    // Removed once run returns, so it is never left behind in /tmp
+    let mut temp_cache = None;
+    if let Some(fixtures) = &fixtures {
+        // A cache of its own, so every source is fetched, and with full bodies. The real
+        // one is left as it was
+        let dir = match tempfile::Builder::new().prefix("kindle-bueno-").tempdir() {
+            Ok(dir) => dir,
+            Err(e) => {
+                error!("Could not create a cache directory: {e}");
+                std::process::exit(1);
+            }
+        };
+        config.cache.dir = dir.path().to_path_buf();
+        temp_cache = Some(dir);
+        match fixtures {
+            Fixtures::Record(dir) => info!("Recording http responses to {}", dir.display()),
+            Fixtures::Replay(dir) => info!("Replaying http responses from {}", dir.display()),
+        }
+    }
+
+    let code = tokio::select! {
+        code = run(args, config, fixtures) => code,
+        // Stopped by hand, still cleaning up
+        _ = tokio::signal::ctrl_c() => 130,
+    };
+    drop(temp_cache);
+    std::process::exit(code);
+}
+
+/// Everything after parsing the arguments and the config, returns the exit code
+async fn run(args: Args, config: Config, fixtures: Option<Fixtures>) -> i32 {
+    let http = match http::Http::from_config(&config.http, &config.cache.dir) {
+        Ok(http) => match fixtures {
+            Some(fixtures) => http.with_fixtures(fixtures),
+            None => http,
+        },
+        Err(e) => {
+            error!("{e}");
+            return 1;
+        }
+    };
+    let secrets = match Secrets::load(&config.secrets) {
+        Ok(secrets) => secrets,
+        Err(e) => {
+            error!("{e}");
+            return 1;
+        }
+    };
+    let registry = Registry::from_config(&config, &http, &secrets);
+    let network = Network::from_config(&config.network, &http);
+
+    if let Some(name) = &args.source {
+        network.connect().await;
+        match registry.fetch_json(name).await {
+            Ok(value) => println!("{value:#}"),
+            Err(e) => {
+                error!("{name}: {e}");
+                return 1;
+            }
+        }
+        return 0;
+    }
+
+    // Shows frames and errors with eips
+    let eips = env::var("NOT_KINDLE").is_err() && !args.no_display;
+    if args.check {
+        let ok = check(&registry, eips);
+        return if ok { 0 } else { 1 };
+    }
+    if eips {
+        if let Err(e) = utils::check_eips() {
+            error!("{e}");
+            return 1;
+        }
     }
 
-    panic_wrapper().await.ok();
+    let device = device::resolve(&config.device.model);
+    let mut display = display::from_config(&config.display, &device);
+    let mut dashboard = Dashboard::new(&config, device);
+    let power = Power::from_config(&config.power);
+
+    let result = update(
+        &config,
+        &registry,
+        &network,
+        &mut dashboard,
+        display.as_mut(),
+        true,
+    )
+    .await;
+    let failed = result.is_err();
+    handle(result, eips).await;
+    if args.once {
+        return if failed { 1 } else { 0 };
+    }
 
     loop {
-        let wait = get_duration_until_next_interval();
+        let wait = get_duration_until_next_interval(config.rotate_minutes());
         let minutes = wait / 60;
         info!("Sleeping for {minutes} minutes...");
-        tokio::time::sleep(std::time::Duration::from_secs(wait)).await;
-        panic_wrapper().await.ok();
+        power.wait(Duration::from_secs(wait)).await;
+
+        // Pages rotate every rotate_minutes, but data is only refetched every sleep_minutes
+        let refresh = dashboard.fetch_due(config.sleep_minutes);
+        let result = update(
+            &config,
+            &registry,
+            &network,
+            &mut dashboard,
+            display.as_mut(),
+            refresh,
+        )
+        .await;
+        handle(result, eips).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fetch_due() {
+        let mut dashboard = Dashboard::new(&Config::default(), device::resolve("desktop"));
+        assert!(dashboard.fetch_due(60));
+
+        dashboard.fetched_at = Some(SystemTime::now() - Duration::from_secs(30 * 60));
+        assert!(!dashboard.fetch_due(60));
+        // Woken a few seconds early, or an hour late after a suspend
+        dashboard.fetched_at = Some(SystemTime::now() - Duration::from_secs(59 * 60 + 50));
+        assert!(dashboard.fetch_due(60));
+        dashboard.fetched_at = Some(SystemTime::now() - Duration::from_secs(121 * 60));
+        assert!(dashboard.fetch_due(60));
     }
 }
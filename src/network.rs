@@ -0,0 +1,156 @@
+use crate::config::NetworkConfig;
+use crate::http::Http;
+
+use log::{info, warn};
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+const POLL: Duration = Duration::from_secs(2);
+const PROBE_RETRIES: u32 = 3;
+const PROBE_DELAY: Duration = Duration::from_secs(5);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/*
+    Gets the kindle online before fetching: turns Wi-Fi on, waits until it is associated
+    and has an address, then checks that the probe url answers. Never fails, being offline
+    is reported so the dashboard can show the cached data marked as such.
+*/
+pub struct Network {
+    config: NetworkConfig,
+    http: Http,
+    /// Off when running on a desktop, which is assumed to be online already
+    manage_wifi: bool,
+}
+
+impl Network {
+    pub fn from_config(config: &NetworkConfig, http: &Http) -> Self {
+        let manage_wifi = config.manage_wifi && std::env::var("NOT_KINDLE").is_err();
+        Network {
+            config: config.clone(),
+            http: http.clone(),
+            manage_wifi,
+        }
+    }
+
+    /// Whether the kindle is online, after waiting for Wi-Fi to connect
+    pub async fn connect(&self) -> bool {
+        if self.http.replaying() {
+            return true;
+        }
+        let now = Instant::now();
+
+        if self.manage_wifi && !self.wait_for_wifi().await {
+            warn!(
+                "Wi-Fi did not connect within {}s",
+                self.config.connect_timeout_seconds
+            );
+        }
+        let online = self.probe().await;
+
+        let elapsed = format!("{:.2?}", now.elapsed());
+        if online {
+            info!("Online after {elapsed}");
+        } else {
+            warn!("Offline after {elapsed}, showing cached data");
+        }
+        online
+    }
+
+    async fn wait_for_wifi(&self) -> bool {
+        let timeout = Duration::from_secs(self.config.connect_timeout_seconds);
+        let now = Instant::now();
+
+        set_wifi(true);
+        while now.elapsed() < timeout {
+            if self.connected() {
+                return true;
+            }
+            sleep(POLL).await;
+        }
+        false
+    }
+
+    // Associated and given an address by dhcp, per wpa_supplicant, or just up if there
+    // is no wpa_cli to ask
+    fn connected(&self) -> bool {
+        let interface = &self.config.interface;
+        let output = Command::new("wpa_cli")
+            .args(["-i", interface, "status"])
+            .output();
+        match output {
+            Ok(output) if output.status.success() => {
+                wpa_connected(&String::from_utf8_lossy(&output.stdout))
+            }
+            _ => {
+                let operstate = Path::new("/sys/class/net")
+                    .join(interface)
+                    .join("operstate");
+                std::fs::read_to_string(operstate).is_ok_and(|state| state.trim() == "up")
+            }
+        }
+    }
+
+    async fn probe(&self) -> bool {
+        for attempt in 1..=PROBE_RETRIES {
+            info!("Checking for internet at {}...", self.config.probe_url);
+            // Straight through the client, this loop does its own retrying
+            let probe = self.http.get(&self.config.probe_url).timeout(PROBE_TIMEOUT);
+            match probe.send().await {
+                Ok(_) => return true,
+                Err(e) => warn!("Probe {attempt}/{PROBE_RETRIES} failed: {e}"),
+            }
+            if attempt < PROBE_RETRIES {
+                sleep(PROBE_DELAY).await;
+            }
+        }
+        false
+    }
+}
+
+fn wpa_connected(status: &str) -> bool {
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+    };
+    field("wpa_state") == Some("COMPLETED") && field("ip_address").is_some()
+}
+
+/// Turns Wi-Fi on or off through the kindle framework, falling back to wpa_cli
+pub fn set_wifi(enabled: bool) {
+    let value = if enabled { "1" } else { "0" };
+    let lipc = Command::new("lipc-set-prop")
+        .args(["com.lab126.cmd", "wirelessEnable", value])
+        .status();
+    let e = match lipc {
+        Ok(status) if status.success() => return,
+        Ok(status) => format!("lipc-set-prop exited with {status}"),
+        Err(e) => e.to_string(),
+    };
+
+    let command = if enabled { "reconnect" } else { "disconnect" };
+    match Command::new("wpa_cli").arg(command).status() {
+        Ok(status) if status.success() => {}
+        _ => warn!("Could not turn Wi-Fi {value}: {e}, and wpa_cli {command} failed too"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wpa_connected() {
+        let associating = "bssid=00:11:22:33:44:55\nssid=home\nwpa_state=ASSOCIATING\n";
+        assert!(!wpa_connected(associating));
+
+        // Associated, but dhcp has not handed out an address yet
+        let no_address = "ssid=home\nwpa_state=COMPLETED\nkey_mgmt=WPA2-PSK\n";
+        assert!(!wpa_connected(no_address));
+
+        let connected = "ssid=home\nwpa_state=COMPLETED\nip_address=192.168.1.20\n";
+        assert!(wpa_connected(connected));
+    }
+}
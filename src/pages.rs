@@ -0,0 +1,85 @@
+use chrono::NaiveTime;
+
+use crate::config::PageConfig;
+
+use log::warn;
+
+impl PageConfig {
+    /// Whether the page's time of day window, if it has one, includes `now`
+    pub fn is_shown_at(&self, now: NaiveTime) -> bool {
+        match (self.from, self.until) {
+            (Some(from), Some(until)) if from <= until => from <= now && now < until,
+            // Wraps around midnight, e.g. 22:00 to 06:00
+            (Some(from), Some(until)) => now >= from || now < until,
+            _ => true,
+        }
+    }
+}
+
+/*
+    Which page to show next: every rotation moves on to the next page in config order
+    whose time window includes the current time, so pages without a window take turns
+    and a page with one only joins in during its hours.
+*/
+#[derive(Default)]
+pub struct Rotation {
+    current: Option<usize>,
+}
+
+impl Rotation {
+    pub fn next<'a>(&mut self, pages: &'a [PageConfig], now: NaiveTime) -> &'a PageConfig {
+        let start = self.current.map_or(0, |i| i + 1);
+        let index = (start..start + pages.len())
+            .map(|i| i % pages.len())
+            .find(|&i| pages[i].is_shown_at(now))
+            .unwrap_or_else(|| {
+                warn!("No page is shown at {now}, using the first one");
+                0
+            });
+
+        self.current = Some(index);
+        &pages[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(name: &str, window: Option<(u32, u32)>) -> PageConfig {
+        PageConfig {
+            name: name.to_string(),
+            from: window.and_then(|(from, _)| NaiveTime::from_hms_opt(from, 0, 0)),
+            until: window.and_then(|(_, until)| NaiveTime::from_hms_opt(until, 0, 0)),
+            ..Default::default()
+        }
+    }
+
+    fn names(pages: &[PageConfig], hour: u32, count: usize) -> Vec<String> {
+        let mut rotation = Rotation::default();
+        let now = NaiveTime::from_hms_opt(hour, 0, 0).unwrap();
+        (0..count)
+            .map(|_| rotation.next(pages, now).name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_rotation() {
+        let pages = [
+            page("weather", None),
+            page("morning", Some((7, 9))),
+            page("night", Some((22, 6))),
+        ];
+
+        assert_eq!(
+            names(&pages, 8, 4),
+            ["weather", "morning", "weather", "morning"]
+        );
+        assert_eq!(names(&pages, 12, 2), ["weather", "weather"]);
+        assert_eq!(names(&pages, 23, 3), ["weather", "night", "weather"]);
+        assert_eq!(names(&pages, 3, 2), ["weather", "night"]);
+
+        // Nothing matches, the first page is shown anyway
+        assert_eq!(names(&pages[1..], 12, 2), ["morning", "morning"]);
+    }
+}
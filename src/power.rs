@@ -0,0 +1,134 @@
+use crate::config::PowerConfig;
+use crate::error::Error;
+use crate::network::set_wifi;
+
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// rtc1 is the one that can wake most kindles up, rtc0 the usual one elsewhere
+const WAKEALARM_PATHS: [&str; 2] = [
+    "/sys/class/rtc/rtc1/wakealarm",
+    "/sys/class/rtc/rtc0/wakealarm",
+];
+
+// Shorter waits are not worth turning Wi-Fi off and on for
+const MIN_SUSPEND: Duration = Duration::from_secs(2 * 60);
+
+// The epdc keeps refreshing the panel after the frame is sent, suspending too soon
+// can leave it half drawn
+const SETTLE: Duration = Duration::from_secs(3);
+
+/*
+    Waits between refreshes, by suspending the device to ram with the rtc set to wake it
+    up again in low power mode, or by just sleeping otherwise. Wi-Fi is turned off while
+    suspended, it is the biggest drain left.
+*/
+pub struct Power {
+    low_power: bool,
+    wakealarm: Option<PathBuf>,
+    state: PathBuf,
+    toggle_wifi: bool,
+}
+
+impl Power {
+    pub fn from_config(config: &PowerConfig) -> Self {
+        let wakealarm = config.wakealarm.clone().or_else(|| {
+            WAKEALARM_PATHS
+                .iter()
+                .map(PathBuf::from)
+                .find(|path| path.exists())
+        });
+        if config.low_power && wakealarm.is_none() {
+            warn!("No rtc wakealarm found, low power mode will only sleep");
+        }
+        Power {
+            low_power: config.low_power,
+            wakealarm,
+            state: config.state.clone(),
+            toggle_wifi: config.toggle_wifi,
+        }
+    }
+
+    /// Returns after `wait`, suspending through it in low power mode.
+    pub async fn wait(&self, wait: Duration) {
+        let until = SystemTime::now() + wait;
+
+        if self.low_power && wait >= MIN_SUSPEND {
+            tokio::time::sleep(SETTLE).await;
+            if let Err(e) = self.suspend(wait - SETTLE) {
+                warn!("Could not suspend, sleeping instead: {e}");
+            }
+        }
+
+        // The rtc may wake us a bit early, or suspending failed. Wall clock time, the
+        // monotonic clock does not run while suspended
+        if let Ok(left) = until.duration_since(SystemTime::now()) {
+            tokio::time::sleep(left).await;
+        }
+    }
+
+    /// Suspends to ram until the rtc wakes the device up `wait` from now
+    fn suspend(&self, wait: Duration) -> Result<(), Error> {
+        let Some(wakealarm) = &self.wakealarm else {
+            return Err(Error::Io("no rtc wakealarm".to_string()));
+        };
+        // An alarm that is already set has to be cleared before setting another
+        write(wakealarm, "0")?;
+        write(wakealarm, &format!("+{}", wait.as_secs()))?;
+
+        if self.toggle_wifi {
+            set_wifi(false);
+        }
+        info!("Suspending for {} seconds...", wait.as_secs());
+        let now = SystemTime::now();
+
+        // Blocks until the device wakes up again
+        let result = write(&self.state, "mem");
+
+        let elapsed = format!("{:.0?}", now.elapsed().unwrap_or_default());
+        info!("Woke up after {elapsed}");
+        if self.toggle_wifi {
+            set_wifi(true);
+        }
+        result
+    }
+}
+
+fn write(path: &Path, value: &str) -> Result<(), Error> {
+    std::fs::write(path, value)
+        .map_err(|e| Error::Io(format!("Could not write {}: {e}", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suspend_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+
+        let power = Power::from_config(&PowerConfig {
+            low_power: true,
+            wakealarm: Some(dir.join("wakealarm")),
+            state: dir.join("state"),
+            toggle_wifi: false,
+        });
+        power.suspend(Duration::from_secs(15 * 60)).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("wakealarm")).unwrap(),
+            "+900"
+        );
+        assert_eq!(std::fs::read_to_string(dir.join("state")).unwrap(), "mem");
+
+        // Without an alarm to wake it up again it must not suspend at all
+        std::fs::remove_file(dir.join("state")).unwrap();
+        let power = Power {
+            wakealarm: None,
+            ..power
+        };
+        assert!(power.suspend(Duration::from_secs(60)).is_err());
+        assert!(!dir.join("state").exists());
+    }
+}
@@ -1,18 +1,19 @@
+use async_trait::async_trait;
 use image::{self, ImageBuffer, ImageFormat, Luma};
-use reqwest;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use image::{DynamicImage, GenericImageView};
+use crate::cache;
+use crate::config::{Config, WindConfig};
+use crate::error::Error;
+use crate::http::Http;
+use crate::secrets::Secret;
+use crate::source::DataSource;
 
-use reqwest::header::USER_AGENT;
+use image::{DynamicImage, GenericImageView};
 
 use chrono::{Datelike, Local, Timelike};
 use log::info;
-
-#[derive(Deserialize, Debug)]
-struct AemetKey {
-    key: String,
-}
+use std::time::Duration;
 
 #[derive(Deserialize, Debug)]
 struct AemetRes {
@@ -30,30 +31,10 @@ struct AemetStation {
     wind_direction: f32,
 }
 
-pub async fn get_image(url: &str) -> Result<image::DynamicImage, String> {
-    let client = reqwest::Client::new();
-
-    let img_bytes = match client
-        .get(url)
-        .header(
-            USER_AGENT,
-            "Mozilla/5.0 (Android 4.4; Mobile; rv:41.0) Gecko/41.0 Firefox/41.0",
-        )
-        .send()
-        .await
-    {
-        Ok(response) => match response.bytes().await {
-            Ok(img_bytes) => img_bytes,
-            Err(err) => return Err(format!("Failed to read response bytes: {}", err)),
-        },
-        Err(err) => return Err(format!("Failed to fetch image: {}", err)),
-    };
-
-    let image = match image::load_from_memory_with_format(&img_bytes, ImageFormat::Png) {
-        Ok(img) => img,
-        Err(err) => return Err(format!("Failed to load image: {}", err)),
-    };
+pub async fn get_image(url: &str, http: &Http) -> Result<image::DynamicImage, Error> {
+    let img_bytes = http.send(http.get(url)).await?;
 
+    let image = image::load_from_memory_with_format(img_bytes.bytes(), ImageFormat::Png)?;
     Ok(image)
 }
 
@@ -69,10 +50,42 @@ pub fn get_image_url() -> String {
     url
 }
 
-pub async fn fetch_radar() -> Result<DynamicImage, Box<dyn std::error::Error>> {
+pub struct RadarSource {
+    http: Http,
+}
+
+impl RadarSource {
+    pub fn new(http: &Http) -> Self {
+        RadarSource { http: http.clone() }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RadarMap {
+    #[serde(with = "cache::png")]
+    pub image: DynamicImage,
+}
+
+#[async_trait]
+impl DataSource for RadarSource {
+    type Output = RadarMap;
+    const NAME: &'static str = "radar";
+
+    fn refresh(&self) -> Duration {
+        Duration::from_secs(60 * 60)
+    }
+
+    async fn fetch(&self) -> Result<Self::Output, Error> {
+        Ok(RadarMap {
+            image: fetch_radar(&self.http).await?,
+        })
+    }
+}
+
+pub async fn fetch_radar(http: &Http) -> Result<DynamicImage, Error> {
     let image_url = get_image_url();
     info!("Fetching AEMET radar image: {image_url}");
-    let mut image1 = get_image(&image_url).await?;
+    let mut image1 = get_image(&image_url, http).await?;
     image1 = DynamicImage::ImageLuma8(remap_colors_to_grayscale_fuzzy(&image1));
     Ok(image1)
 }
@@ -122,37 +135,72 @@ fn remap_colors_to_grayscale_fuzzy(img: &DynamicImage) -> ImageBuffer<Luma<u8>,
     gray_img
 }
 
-#[derive(Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Wind {
     pub speed: f32,
     pub direction: f32,
 }
 
-pub async fn fetch_wind() -> Result<Wind, Box<dyn std::error::Error>> {
-    let file = std::fs::File::open("sensitive/aemet.json")?;
-    let json_key: AemetKey = serde_json::from_reader(file)?;
-    let key = json_key.key;
+pub struct WindSource {
+    config: WindConfig,
+    http: Http,
+    key: Secret,
+}
+
+impl WindSource {
+    pub fn new(config: &Config, http: &Http, key: &Secret) -> Self {
+        WindSource {
+            config: config.wind.clone(),
+            http: http.clone(),
+            key: key.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl DataSource for WindSource {
+    type Output = Wind;
+    const NAME: &'static str = "wind";
+    const CREDENTIALS: &'static [&'static str] = &["aemet"];
+
+    fn refresh(&self) -> Duration {
+        Duration::from_secs(30 * 60)
+    }
+
+    async fn fetch(&self) -> Result<Self::Output, Error> {
+        fetch_wind(&self.config, &self.http, &self.key).await
+    }
+}
+
+pub async fn fetch_wind(config: &WindConfig, http: &Http, key: &Secret) -> Result<Wind, Error> {
+    let key = key.expose();
 
     let url = format!(
-        "https://opendata.aemet.es/opendata/api/observacion/convencional/datos/estacion/C029O?api_key={key}"
+        "https://opendata.aemet.es/opendata/api/observacion/convencional/datos/estacion/{}?api_key={key}",
+        config.station
     );
 
-    let client = reqwest::Client::new();
-
-    let response = client.get(url).send().await?;
-    let ares: AemetRes = response.json().await?;
+    let response = http.send(http.get(url)).await?;
+    let ares: AemetRes = response.json()?;
 
     if ares.status != 200 {
-        return Err(format!("aemet status {}", ares.status).into());
+        return Err(Error::Status {
+            url: "opendata.aemet.es".to_string(),
+            status: u16::try_from(ares.status)
+                .map_err(|_| Error::Parse(format!("aemet status {}", ares.status)))?,
+        });
     }
 
     let url = ares.data;
 
-    let response = client.get(url).send().await?;
-    let data: Vec<AemetStation> = response.json().await?;
+    let response = http.send(http.get(url)).await?;
+    let data: Vec<AemetStation> = response.json()?;
 
     let Some(last) = data.last() else {
-        return Err(format!("no aemet data").into());
+        return Err(Error::Parse(format!(
+            "aemet data, no observations for {}",
+            config.station
+        )));
     };
     Ok(Wind {
         speed: last.wind_speed,
@@ -0,0 +1,182 @@
+use image::GrayImage;
+
+use log::info;
+
+// Changes closer than this are refreshed as one rectangle, a few big updates are
+// quicker for the epdc than many small ones
+const MERGE_GAP: u32 = 16;
+
+// Past this share of the screen a partial refresh ghosts as much as it saves
+const MAX_PARTIAL_AREA: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Update {
+    /// Flash the whole panel, clears ghosting
+    Full,
+    /// Only redraw these regions, without flashing
+    Partial(Vec<Rect>),
+}
+
+// Half open ranges of `changed` that are true, joining ranges less than `gap` apart
+fn spans(changed: impl Iterator<Item = bool>, gap: u32) -> Vec<(u32, u32)> {
+    let mut spans: Vec<(u32, u32)> = vec![];
+    for (i, changed) in (0..).zip(changed) {
+        if !changed {
+            continue;
+        }
+        match spans.last_mut() {
+            Some((_, end)) if i - *end < gap => *end = i + 1,
+            _ => spans.push((i, i + 1)),
+        }
+    }
+    spans
+}
+
+/// Regions that differ between two frames of the same size
+pub fn dirty_rects(previous: &GrayImage, next: &GrayImage) -> Vec<Rect> {
+    let width = next.width() as usize;
+    let rows = previous
+        .as_raw()
+        .chunks_exact(width)
+        .zip(next.as_raw().chunks_exact(width));
+    let bands = spans(rows.map(|(a, b)| a != b), MERGE_GAP);
+
+    let mut rects = vec![];
+    for (top, bottom) in bands {
+        let columns = (0..next.width())
+            .map(|x| (top..bottom).any(|y| previous.get_pixel(x, y) != next.get_pixel(x, y)));
+        for (left, right) in spans(columns, MERGE_GAP) {
+            rects.push(Rect {
+                left,
+                top,
+                width: right - left,
+                height: bottom - top,
+            });
+        }
+    }
+    rects
+}
+
+/*
+    Decides how much of the panel to refresh, by diffing each frame against the one
+    on screen. Every `full_every` updates it flashes the whole panel anyway.
+*/
+pub struct Refresher {
+    full_every: u32,
+    previous: Option<GrayImage>,
+    since_full: u32,
+}
+
+impl Refresher {
+    pub fn new(full_every: u32) -> Self {
+        Refresher {
+            full_every,
+            previous: None,
+            since_full: 0,
+        }
+    }
+
+    /// What to refresh to show `image`, or None if it is already on screen.
+    ///
+    /// Forgets the previous frame until `shown` is called, so if showing fails
+    /// the next update is a full one.
+    pub fn plan(&mut self, image: &GrayImage) -> Option<Update> {
+        let Some(previous) = self.previous.take() else {
+            return Some(Update::Full);
+        };
+        if previous.dimensions() != image.dimensions() || self.since_full + 1 >= self.full_every {
+            return Some(Update::Full);
+        }
+
+        let rects = dirty_rects(&previous, image);
+        if rects.is_empty() {
+            self.previous = Some(previous);
+            return None;
+        }
+
+        let area: u32 = rects.iter().map(|r| r.width * r.height).sum();
+        let screen = image.width() * image.height();
+        if area as f64 > screen as f64 * MAX_PARTIAL_AREA {
+            info!("Most of the screen changed, doing a full refresh");
+            return Some(Update::Full);
+        }
+        Some(Update::Partial(rects))
+    }
+
+    pub fn shown(&mut self, image: GrayImage, update: &Update) {
+        self.since_full = match update {
+            Update::Full => 0,
+            Update::Partial(_) => self.since_full + 1,
+        };
+        self.previous = Some(image);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(dark: &[(u32, u32)]) -> GrayImage {
+        let mut image = GrayImage::from_pixel(200, 100, image::Luma([255]));
+        for &(x, y) in dark {
+            image.put_pixel(x, y, image::Luma([0]));
+        }
+        image
+    }
+
+    #[test]
+    fn test_dirty_rects() {
+        let previous = frame(&[]);
+        assert!(dirty_rects(&previous, &previous).is_empty());
+
+        // Two nearby changes are merged, a far away one is not
+        let next = frame(&[(10, 10), (20, 15), (150, 80)]);
+        assert_eq!(
+            dirty_rects(&previous, &next),
+            [
+                Rect {
+                    left: 10,
+                    top: 10,
+                    width: 11,
+                    height: 6
+                },
+                Rect {
+                    left: 150,
+                    top: 80,
+                    width: 1,
+                    height: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_refresher() {
+        let mut refresher = Refresher::new(3);
+        let clock = |minute: u32| frame(&[(minute, 5)]);
+
+        assert_eq!(refresher.plan(&clock(0)), Some(Update::Full));
+        refresher.shown(clock(0), &Update::Full);
+
+        // Unchanged frames are skipped and do not count towards the full refresh
+        assert_eq!(refresher.plan(&clock(0)), None);
+
+        for minute in 1..3 {
+            let update = refresher.plan(&clock(minute)).unwrap();
+            assert!(matches!(&update, Update::Partial(rects) if rects.len() == 1));
+            refresher.shown(clock(minute), &update);
+        }
+        assert_eq!(refresher.plan(&clock(3)), Some(Update::Full));
+
+        // Not marked as shown, so the next one cannot be partial either
+        assert_eq!(refresher.plan(&clock(4)), Some(Update::Full));
+    }
+}
@@ -1,499 +1,494 @@
-use crate::radar;
-use crate::radar::Wind;
-use crate::stats;
-use crate::stats::tides::Tide;
-use crate::weather;
-
-use crate::weather::DayData;
-
-use image::{DynamicImage, ImageBuffer, Rgba};
-use resvg;
+use crate::assets::{self, Assets};
+use crate::battery::BatterySource;
+use crate::chart;
+use crate::config::{Config, PageConfig};
+use crate::device::Device;
+use crate::disk;
+use crate::display::Display;
+use crate::dither;
+use crate::error::Error;
+use crate::radar::{RadarMap, RadarSource, WindSource};
+use crate::refresh::{Rect, Refresher, Update};
+use crate::source::{DataSource, KindleDisplayData, SOURCE_NAMES};
+use crate::stats::tides::{self, Tide, TidesSource};
+use crate::stats::MoonSource;
+use crate::template::{Bindings, Template, Value};
+use crate::weather::{day_name, DayData, WeatherSource};
+
+use image::{DynamicImage, GrayImage, ImageBuffer, Rgba};
 use tiny_skia::{PixmapMut, Transform, BYTES_PER_PIXEL};
 use usvg::Tree;
 
-use std::{env, process::Command};
+use std::collections::BTreeSet;
+use std::process::Command;
 
 use base64::prelude::*;
-use regex::Regex;
 use std::io::Cursor;
 
-use chrono::Timelike;
+use chrono::{DateTime, Datelike, Timelike};
+use chrono_tz::Tz;
+use std::time::Duration as stdDuration;
 use std::time::Instant;
 
-use futures::join;
 use log::{info, warn};
 
-use async_std::future;
-use std::time::Duration as stdDuration;
+// Number of forecast cards in the template
+const FORECAST_DAYS: usize = 3;
 
-#[derive(Debug)]
-struct KindleDisplayData {
-    short_stats: Option<stats::Stats>,
-    weather: Option<Vec<weather::DayData>>,
-    image: Option<DynamicImage>,
-    wind: Option<Wind>,
+fn tide_binding(tide: &Tide) -> Value {
+    let (name, time) = match tide {
+        Tide::High(time) => ("Pleamar", time),
+        Tide::Low(time) => ("Bajamar", time),
+    };
+    Value::map([
+        ("name", Value::from(name)),
+        ("time", Value::from(time.as_str())),
+    ])
 }
 
-async fn build_all_data() -> KindleDisplayData {
-    info!("Fetching all data...");
-    let now = Instant::now();
-
-    let timeout = stdDuration::from_secs(30);
-
-    let (short_stats, weather, image, wind) = join!(
-        future::timeout(timeout, stats::fetch_stats()),
-        future::timeout(timeout, weather::fetch_weather()),
-        future::timeout(timeout, radar::fetch_radar()),
-        future::timeout(timeout, radar::fetch_wind()),
-    );
-
-    let elapsed = format!("{:.2?}", now.elapsed());
-    info!("Fetched all kindle data in {elapsed}");
-
-    // Checking timeout messages
-    let short_stats = match short_stats {
-        Ok(r) => r,
-        Err(e) => Err(format!("Timeout: {e}").into()),
-    };
-    let weather = match weather {
-        Ok(r) => r,
-        Err(e) => Err(format!("Timeout: {e}").into()),
-    };
-    let image = match image {
-        Ok(r) => r,
-        Err(e) => Err(format!("Timeout: {e}").into()),
-    };
-    let wind = match wind {
-        Ok(r) => r,
-        Err(e) => Err(format!("Timeout: {e}").into()),
+fn tides_binding(data: &KindleDisplayData, now: &DateTime<Tz>) -> Value {
+    let Some(table) = data.get::<TidesSource>() else {
+        return Value::None;
     };
 
-    // Warning on error
-    match &short_stats {
-        Ok(_) => {}
-        Err(e) => warn!("Short stats failed: {e}"),
-    }
-    match &weather {
-        Ok(_) => {}
-        Err(e) => warn!("Weather failed: {e}"),
-    }
-    match &image {
-        Ok(_) => {}
-        Err(e) => warn!("Radar failed: {e}"),
-    }
-    match &wind {
-        Ok(_) => {}
-        Err(e) => warn!("Aemet failed: {e}"),
+    match tides::get_two_tides(&table.entries, now.time()) {
+        Ok((first, second)) => Value::map([
+            ("first", tide_binding(&first)),
+            ("second", tide_binding(&second)),
+        ]),
+        Err(e) => {
+            warn!("Could not pick the next tides: {e}");
+            Value::map([("first", Value::None), ("second", Value::None)])
+        }
     }
+}
 
-    KindleDisplayData {
-        short_stats: short_stats.ok(),
-        weather: weather.ok(),
-        image: image.ok(),
-        wind: wind.ok(),
+fn moon_binding(data: &KindleDisplayData, assets: &Assets) -> Value {
+    match data.get::<MoonSource>() {
+        Some(phase) => icon_binding(moon_to_icon(*phase, assets)),
+        None => Value::None,
     }
 }
 
-fn format_stats(template: String, data: &KindleDisplayData) -> String {
-    let mut template = template.clone();
-    match &data.short_stats {
-        Some(short_stats) => {
-            template = template.replace(
-                "#I1a",
-                &match &short_stats.tides {
-                    Some((first, _)) => match first {
-                        Tide::High(_) => format!("Pleamar"),
-                        Tide::Low(_) => format!("Bajamar"),
-                    },
-                    None => "NA".to_string(),
-                },
-            );
-            template = template.replace(
-                "#I1b",
-                &match &short_stats.tides {
-                    Some((first, _)) => match first {
-                        Tide::High(time) => format!("{time}"),
-                        Tide::Low(time) => format!("{time}"),
-                    },
-                    None => "NA".to_string(),
-                },
-            );
+fn age_badge(age: Option<stdDuration>) -> String {
+    let Some(age) = age else {
+        return String::new();
+    };
+    let minutes = age.as_secs() / 60;
+    if minutes < 60 {
+        format!("{minutes}m ago")
+    } else if minutes < 48 * 60 {
+        format!("{}h ago", minutes / 60)
+    } else {
+        format!("{}d ago", minutes / (24 * 60))
+    }
+}
 
-            template = template.replace(
-                "#I2a",
-                &match &short_stats.tides {
-                    Some((_, second)) => match second {
-                        Tide::High(_) => format!("Pleamar"),
-                        Tide::Low(_) => format!("Bajamar"),
-                    },
-                    None => "NA".to_string(),
-                },
-            );
-            template = template.replace(
-                "#I2b",
-                &match &short_stats.tides {
-                    Some((_, second)) => match second {
-                        Tide::High(time) => format!("{time}"),
-                        Tide::Low(time) => format!("{time}"),
-                    },
-                    None => "NA".to_string(),
-                },
-            );
+// Most problems listed in the banner, the rest are summed up
+const MAX_BANNER_ERRORS: usize = 3;
 
-            template = replace_image(
-                template,
-                "moon/1.svg",
-                &moon_to_icon(short_stats.moon_phase),
-            );
+// Problems that still leave a dashboard to show, for the banner at the bottom
+fn errors_binding(data: &KindleDisplayData, config: &Config) -> Value {
+    let mut errors = vec![];
+    if data.offline {
+        errors.push("Offline, showing cached data".to_string());
+    }
 
-            // template = template.replace(
-            // "#I4",
-            // &match short_stats.linux_share {
-            // Some(v) => format!("{:.2}%", v),
-            // None => "NA".to_string(),
-            // },
-            // );
-            //
-            // template = template.replace(
-            // "#I5",
-            // &match short_stats.btc_halving {
-            // Some(v) => time_remaining(v),
-            // None => "NA".to_string(),
-            // },
-            // );
-            //
-            // template = template.replace(
-            // "#I6",
-            // &match short_stats.kernel_version.clone() {
-            // Some(v) => v,
-            // None => "NA".to_string(),
-            // },
-            // );
-        }
-        None => {
-            template = template.replace("#I1", "ERR");
-            template = template.replace("#I2", "ERR");
-            template = template.replace("#I3", "ERR");
-            template = template.replace("#I4", "ERR");
-            template = template.replace("#I5", "ERR");
-            template = template.replace("#I6", "ERR");
+    let mut failed: Vec<_> = data
+        .errors()
+        // Being offline already explains these
+        .filter(|(_, e)| !(data.offline && matches!(e, Error::Network(_))))
+        .map(|(name, e)| format!("{name}: {}", e.summary()))
+        .collect();
+    failed.sort();
+    errors.extend(failed);
+
+    let min_free = config.cache.min_free_mb * 1024 * 1024;
+    match disk::free_space(&config.cache.dir) {
+        Some(free) if free < min_free => {
+            warn!("Only {} KB of disk space left", free / 1024);
+            errors.push(format!("Low disk space, {} MB left", free / 1024 / 1024));
         }
-    };
+        _ => {}
+    }
 
-    return template;
+    if errors.len() > MAX_BANNER_ERRORS {
+        let more = errors.len() - (MAX_BANNER_ERRORS - 1);
+        errors.truncate(MAX_BANNER_ERRORS - 1);
+        errors.push(format!("{more} more"));
+    }
+    Value::List(errors.into_iter().map(Value::from).collect())
 }
 
-fn format_time(template: String, _data: &KindleDisplayData) -> String {
-    // We assume that making the primary requests take less than a minute to create the nice "every 15 minute" effect.
-
-    let mut template = template.clone();
-
-    // let now = chrono::offset::Utc::now() + Duration::hours(10);
-    let now = chrono::offset::Utc::now().with_timezone(&chrono_tz::Atlantic::Canary);
+// Only shows up for data older than its source's refresh interval
+fn ages_binding(data: &KindleDisplayData) -> Value {
+    Value::map([
+        (
+            "weather",
+            age_badge(data.stale_age::<WeatherSource>()).into(),
+        ),
+        ("tides", age_badge(data.stale_age::<TidesSource>()).into()),
+        ("wind", age_badge(data.stale_age::<WindSource>()).into()),
+        ("radar", age_badge(data.stale_age::<RadarSource>()).into()),
+    ])
+}
 
-    let hour = now.hour();
-    let minute = now.minute();
+fn battery_binding(data: &KindleDisplayData, config: &Config) -> Value {
+    let Some(battery) = data.get::<BatterySource>() else {
+        return Value::None;
+    };
 
-    template = template.replace("#time", &format!("{:0>2}:{:0>2}", hour, minute));
-    // template = template.replace("#2", &format!("{:0>2}", minute));
-    return template;
+    Value::map([
+        ("percent", Value::from(battery.percent as u32)),
+        ("charging", Value::from(battery.charging)),
+        (
+            "low",
+            Value::from(!battery.charging && battery.percent < config.battery.low_percent),
+        ),
+    ])
 }
 
-fn weather_to_icon(day: &DayData) -> String {
-    let icon1 = include_str!("icons/1.svg").to_string();
-    let icon2 = include_str!("icons/2.svg").to_string();
-    let icon3 = include_str!("icons/3.svg").to_string();
-    let icon4 = include_str!("icons/4.svg").to_string();
-    let icon5 = include_str!("icons/5.svg").to_string();
-    let icon6 = include_str!("icons/6.svg").to_string();
-    let icon7 = include_str!("icons/7.svg").to_string();
-    let icon8 = include_str!("icons/8.svg").to_string();
+fn time_binding(now: &DateTime<Tz>) -> Value {
+    Value::map([
+        ("hour", Value::from(now.hour())),
+        ("minute", Value::from(now.minute())),
+    ])
+}
 
+fn weather_to_icon(day: &DayData, assets: &Assets) -> Result<String, Error> {
     let avg_rain = day.rain_sum / day.data_points as f64;
     let avg_cloud = day.cloud_sum / day.data_points as f64;
 
-    let mut result = icon1;
+    let mut result = 1;
 
     if avg_cloud > 20.0 {
-        result = icon2
+        result = 2
     }
     if avg_cloud > 50.0 {
-        result = icon3
+        result = 3
     }
     if avg_cloud > 80.0 {
-        result = icon4
+        result = 4
     }
 
     if avg_rain > 0.1 {
-        result = icon5
+        result = 5
     }
     if avg_rain > 0.5 {
-        result = icon6
+        result = 6
     }
     if avg_rain > 1.0 {
-        result = icon7
+        result = 7
     }
     if avg_rain > 5.0 {
-        result = icon8
+        result = 8
     }
 
-    result
+    Ok(assets.get(&format!("icons/{result}.svg"))?.into_owned())
 }
 
 // (0 = new moon, 0.5 = full moon)
-fn moon_to_icon(phase: f64) -> String {
+fn moon_to_icon(phase: f64, assets: &Assets) -> Result<String, Error> {
     let mut phases = [
-        (0.0, include_str!("moon/1.svg").to_string()),
-        (0.125, include_str!("moon/2.svg").to_string()),
-        (0.25, include_str!("moon/3.svg").to_string()),
-        (0.375, include_str!("moon/4.svg").to_string()),
-        (0.5, include_str!("moon/5.svg").to_string()),
-        (0.625, include_str!("moon/6.svg").to_string()),
-        (0.75, include_str!("moon/7.svg").to_string()),
-        (0.875, include_str!("moon/8.svg").to_string()),
+        (0.0, 1),
+        (0.125, 2),
+        (0.25, 3),
+        (0.375, 4),
+        (0.5, 5),
+        (0.625, 6),
+        (0.75, 7),
+        (0.875, 8),
     ];
     phases.reverse();
     // .reverse();
 
-    let mut closest = phases[0].1.clone();
+    let mut closest = phases[0].1;
     let mut smallest_diff = 1.0;
-    for &(p, ref url) in phases.iter() {
+    for &(p, icon) in phases.iter() {
         let diff = (phase - p).abs();
         if diff < smallest_diff {
             smallest_diff = diff;
-            closest = url.clone();
+            closest = icon;
         }
     }
-    closest
+    Ok(assets.get(&format!("moon/{closest}.svg"))?.into_owned())
 }
 
-fn format_weather(template: String, data: &KindleDisplayData) -> String {
-    let mut template = template.clone();
+// A missing icon leaves its spot empty, the rest of the dashboard still renders
+fn icon_binding(icon: Result<String, Error>) -> Value {
+    match icon {
+        Ok(svg) => Value::Raw(svg),
+        Err(e) => {
+            warn!("{e}");
+            Value::None
+        }
+    }
+}
 
-    match &data.weather {
-        Some(weather) => {
-            // Trust me, I'm not happy with this code either
+fn day_binding(day: &DayData, assets: &Assets) -> Value {
+    Value::map([
+        ("date", Value::from(day.date.day())),
+        ("name", Value::from(day_name(day.date.weekday()))),
+        ("max", Value::from(day.max_c)),
+        ("min", Value::from(day.min_c)),
+        ("icon", icon_binding(weather_to_icon(day, assets))),
+    ])
+}
 
-            template = match weather.get(0) {
-                Some(day) => {
-                    template = template.replace("#D1", &format!("{:0>2} {}", day.date, day.day));
-                    template = template.replace("#T1", &format!("{:.1}", day.max_c));
-                    template = template.replace("#T2", &format!("{:.1}", day.min_c));
-                    template = replace_image(template, "icons/1.svg", &&weather_to_icon(day));
+fn weather_binding(data: &KindleDisplayData, assets: &Assets) -> Value {
+    let Some(weather) = data.get::<WeatherSource>() else {
+        return Value::None;
+    };
 
-                    template
-                }
-                None => {
-                    template = template.replace("#D1", "NA");
-                    template = template.replace("#T1", "NA");
-                    template = template.replace("#T2", "NA");
-                    template
-                }
-            };
-
-            template = match weather.get(1) {
-                Some(day) => {
-                    template = template.replace("#D2", &format!("{:0>2} {}", day.date, day.day));
-                    template = template.replace("#T3", &format!("{:.1}", day.max_c));
-                    template = template.replace("#T4", &format!("{:.1}", day.min_c));
-                    template = replace_image(template, "icons/2.svg", &&weather_to_icon(day));
-                    template
-                }
-                None => {
-                    template = template.replace("#D2", "NA");
-                    template = template.replace("#T3", "NA");
-                    template = template.replace("#T4", "NA");
-                    template
-                }
-            };
-
-            template = match weather.get(2) {
-                Some(day) => {
-                    template = template.replace("#D3", &format!("{:0>2} {}", day.date, day.day));
-                    template = template.replace("#T5", &format!("{:.1}", day.max_c));
-                    template = template.replace("#T6", &format!("{:.1}", day.min_c));
-                    template = replace_image(template, "icons/3.svg", &&weather_to_icon(day));
-                    template
-                }
-                None => {
-                    template = template.replace("#D3", "NA");
-                    template = template.replace("#T5", "NA");
-                    template = template.replace("#T6", "NA");
-                    template
-                }
-            };
+    // Always one entry per card, None for days the forecast does not reach
+    let days: Vec<Value> = (0..FORECAST_DAYS)
+        .map(|i| match weather.days.get(i) {
+            Some(day) => day_binding(day, assets),
+            None => Value::None,
+        })
+        .collect();
+    Value::map([("days", Value::List(days))])
+}
+
+fn hourly_binding(data: &KindleDisplayData, config: &Config, now: &DateTime<Tz>) -> Value {
+    let Some(weather) = data.get::<WeatherSource>() else {
+        return Value::None;
+    };
+    match chart::hourly(&weather.hours, now, config.weather.chart_hours) {
+        Some(svg) => Value::Raw(svg),
+        None => Value::None,
+    }
+}
+
+fn radar_binding(data: &KindleDisplayData) -> Value {
+    let Some(RadarMap { image }) = data.get::<RadarSource>() else {
+        return Value::None;
+    };
+
+    let mut buffer = Cursor::new(Vec::new());
+    match image.write_to(&mut buffer, image::ImageFormat::Png) {
+        Ok(_r) => {
+            let encoded_image = BASE64_STANDARD.encode(buffer.get_ref());
+            Value::from(format!("data:image/png;base64,{encoded_image}"))
         }
-        None => {
-            template = template.replace("#D1", "ERR");
-            template = template.replace("#D2", "ERR");
-            template = template.replace("#D3", "ERR");
-            template = template.replace("#T1", "ERR");
-            template = template.replace("#T2", "ERR");
-            template = template.replace("#T3", "ERR");
-            template = template.replace("#T4", "ERR");
-            template = template.replace("#T5", "ERR");
-            template = template.replace("#T6", "ERR");
+        Err(e) => {
+            warn!("Could not write to buffer: {e}");
+            Value::None
         }
+    }
+}
+
+fn wind_binding(data: &KindleDisplayData) -> Value {
+    let Some(wind) = data.get::<WindSource>() else {
+        return Value::None;
     };
 
-    return template;
+    info!("Wind direction: {} deg", wind.direction);
+    Value::map([
+        ("speed", Value::from(wind.speed)),
+        ("direction", Value::from(wind.direction)),
+    ])
 }
 
-fn format_radar(template: String, data: &KindleDisplayData) -> String {
-    let mut template = template.clone();
-    match &data.image {
-        Some(image) => {
-            let mut buffer = Cursor::new(Vec::new());
-
-            let r = image.write_to(&mut buffer, image::ImageFormat::Png);
-            match r {
-                Ok(_r) => {
-                    let encoded_image = BASE64_STANDARD.encode(buffer.get_ref());
-                    template = template
-                        .replace("map.png", &format!("data:image/png;base64,{encoded_image}"));
-                }
-                Err(e) => {
-                    warn!("Could not write to buffer: {e}")
-                }
+// Only computes what the template reads, a custom one may leave out whole widgets
+fn bindings(
+    data: &KindleDisplayData,
+    config: &Config,
+    page: &PageConfig,
+    now: &DateTime<Tz>,
+    assets: &Assets,
+    names: &BTreeSet<String>,
+) -> Bindings {
+    let mut bindings = Bindings::default();
+    for name in names {
+        // Sources the page does not list are bound as missing, so a template that guards
+        // them with `{% if %}` works on any page. Widgets drawn from a source count as
+        // that source
+        let source = match name.as_str() {
+            "hourly" => WeatherSource::NAME,
+            name => name,
+        };
+        if let Some(sources) = &page.sources {
+            if SOURCE_NAMES.contains(&source) && !sources.iter().any(|s| s == source) {
+                bindings.insert(name, Value::None);
+                continue;
             }
         }
-        None => {}
-    };
+        let value = match name.as_str() {
+            "weather" => weather_binding(data, assets),
+            "hourly" => hourly_binding(data, config, now),
+            "tides" => tides_binding(data, now),
+            "moon" => moon_binding(data, assets),
+            "radar" => radar_binding(data),
+            "wind" => wind_binding(data),
+            "battery" => battery_binding(data, config),
+            "time" => time_binding(now),
+            "ages" => ages_binding(data),
+            "offline" => Value::from(data.offline),
+            "errors" => errors_binding(data, config),
+            // Left unbound, rendering reports it with its line number
+            _ => continue,
+        };
+        bindings.insert(name, value);
+    }
+    bindings
+}
 
-    // if let Some(wind) = &data.wind {
-    // template = template.replace("#wind", &format!("{:.2?} m/s", wind.speed));
-    // template = template.replace(
-    // "rotate(45 1115 84)",
-    // &format!("rotate({:.0} 1115 84)", wind.direction + 180.0),
-    // );
-    // info!("Wind direction: {} deg", wind.direction);
-    // } else {
-    // template = template.replace("#wind", "N/A");
-    // }
-    template = template.replace(
-        "rotate(45 1115 84)",
-        &format!("rotate({:.0} 1100 70)", 200.0),
-    );
+fn fill_template(
+    src: &str,
+    data: &KindleDisplayData,
+    config: &Config,
+    page: &PageConfig,
+    now: &DateTime<Tz>,
+    assets: &Assets,
+) -> Result<String, String> {
+    let template = Template::parse(src)?;
+    let names = template.names();
+    template.render(&bindings(data, config, page, now, assets, &names))
+}
 
-    return template;
+fn create_output_svg(
+    config: &Config,
+    data: &KindleDisplayData,
+    page: &PageConfig,
+    now: &DateTime<Tz>,
+) -> Result<String, Error> {
+    let assets = Assets::new(&config.assets.dir);
+
+    match assets.custom(&page.template) {
+        Some(src) => match fill_template(&src, data, config, page, now, &assets) {
+            Ok(svg) => return Ok(svg),
+            Err(e) => warn!(
+                "Invalid {} in {}, using the built-in one: {e}",
+                page.template,
+                config.assets.dir.display()
+            ),
+        },
+        None if page.template != assets::TEMPLATE => warn!(
+            "No {} in {}, using the built-in template",
+            page.template,
+            config.assets.dir.display()
+        ),
+        None => {}
+    }
+    fill_template(
+        assets::embedded(assets::TEMPLATE)?,
+        data,
+        config,
+        page,
+        now,
+        &assets,
+    )
+    .map_err(|e| Error::Render(format!("the built-in template: {e}")))
 }
 
-struct Screen {
-    width: u32,
-    height: u32,
+// Maps svg coordinates straight onto the panel, so nothing is resized afterwards: stretched
+// to the screen and, on the kindle, turned a quarter clockwise onto the portrait panel.
+// Returns the transform and the size of the pixmap to render into.
+fn screen_transform(svg: usvg::Size, device: &Device) -> (Transform, u32, u32) {
+    let (width, height) = (device.height, device.width);
+    let scale = Transform::from_scale(width as f32 / svg.width(), height as f32 / svg.height());
+    if device.rotate {
+        // (x, y) ends up at (height - y, x)
+        let quarter = Transform::from_row(0.0, 1.0, -1.0, 0.0, height as f32, 0.0);
+        (scale.post_concat(quarter), height, width)
+    } else {
+        (scale, width, height)
+    }
 }
 
-fn get_screen_dim() -> Option<Screen> {
-    // Run xrandr to get screen data
-    let output = Command::new("xrandr").output();
-
-    match output {
-        Ok(output) => {
-            let output_str = String::from_utf8_lossy(&output.stdout);
-
-            // regex to get the current screen size
-            let re = Regex::new(r"current (\d+) x (\d+)").expect("Failed to compile regex");
-
-            if let Some(caps) = re.captures(&output_str) {
-                let width = caps.get(1).map_or("", |m| m.as_str()).parse::<u32>();
-                let height = caps.get(2).map_or("", |m| m.as_str()).parse::<u32>();
-
-                match width {
-                    Ok(width) => match height {
-                        Ok(height) => Some(Screen { width, height }),
-                        Err(e) => {
-                            warn!("Could not determine screen size (height) from: {output_str} due to {e}");
-                            None
-                        }
-                    },
-                    Err(e) => {
-                        warn!(
-                            "Could not determine screen size (width) from: {output_str} due to {e}"
-                        );
-                        None
-                    }
-                }
-            } else {
-                None
+// Bitmaps in the svg, in screen pixels, which get dithered instead of just snapped to the panel's grays
+fn photo_regions(group: &usvg::Group, screen: Transform, regions: &mut Vec<Rect>) {
+    for node in group.children() {
+        match node {
+            usvg::Node::Group(group) => photo_regions(group, screen, regions),
+            usvg::Node::Image(image) if !matches!(image.kind(), usvg::ImageKind::SVG(_)) => {
+                // Not abs_bounding_box(), which applies the image's transform twice in this usvg
+                let transform = image.abs_transform().post_concat(screen);
+                let Some(bbox) = image.bounding_box().transform(transform) else {
+                    continue;
+                };
+                regions.push(Rect {
+                    left: bbox.left().max(0.0) as u32,
+                    top: bbox.top().max(0.0) as u32,
+                    width: bbox.width().ceil() as u32,
+                    height: bbox.height().ceil() as u32,
+                });
             }
-        }
-
-        Err(e) => {
-            warn!("Could not run xrandr to get screen size: {e}");
-            None
+            _ => {}
         }
     }
 }
 
-async fn create_output_svg() -> String {
-    let mut template = include_str!("template.svg").to_string();
+fn rasterize(svg_tree: &Tree, device: &Device) -> Result<(GrayImage, Vec<Rect>), Error> {
+    let (transform, width, height) = screen_transform(svg_tree.size(), device);
 
-    //let data = build_some_data().await;
-    let data = build_all_data().await;
+    let mut photos = vec![];
+    photo_regions(svg_tree.root(), transform, &mut photos);
 
-    template = format_stats(template, &data);
-    template = format_time(template, &data);
-    template = format_weather(template, &data);
-    template = format_radar(template, &data);
+    let mut image: Vec<u8> = vec![0; width as usize * height as usize * BYTES_PER_PIXEL];
+    let mut pixmap = PixmapMut::from_bytes(&mut image, width, height)
+        .ok_or_else(|| Error::Render(format!("a {width}x{height} screen")))?;
+    resvg::render(svg_tree, transform, &mut pixmap);
 
-    template
+    let img_buffer: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_raw(width, height, image)
+        .ok_or_else(|| Error::Render("the pixmap into an image".to_string()))?;
+    let rgb = DynamicImage::ImageRgba8(img_buffer).into_rgb8();
+    Ok((DynamicImage::ImageRgb8(rgb).into_luma8(), photos))
+}
+
+// Svg units per inch of the panel, so physical units in a template (`mm`, `pt`, `in`)
+// come out at their real size however far the svg is stretched onto it
+fn svg_dpi(xml: &usvg::roxmltree::Document, device: &Device) -> f32 {
+    let root = xml.root_element();
+    let width = root
+        .attribute("viewBox")
+        .and_then(|view_box| view_box.split([' ', ',']).filter(|n| !n.is_empty()).nth(2))
+        .or_else(|| root.attribute("width"))
+        .and_then(|width| width.trim_end_matches("px").parse::<f32>().ok())
+        .filter(|width| *width > 0.0);
+    // The svg's width goes along the panel's long side
+    let long_side = device.width.max(device.height) as f32;
+    match width {
+        Some(width) => device.dpi as f32 * width / long_side,
+        None => device.dpi as f32,
+    }
 }
 
-async fn render_svg(template: String) -> DynamicImage {
+// Rendered at the panel's native size and orientation
+async fn render_svg(template: String, device: &Device) -> Result<(GrayImage, Vec<Rect>), Error> {
     let mut fontdb = usvg::fontdb::Database::new();
     fontdb.load_font_data(include_bytes!("fonts/FreeSans.ttf").to_vec());
     fontdb.load_font_data(include_bytes!("fonts/FreeSansBold.ttf").to_vec());
 
-    let mut options = usvg::Options::default();
-    options.fontdb = std::sync::Arc::new(fontdb);
-
-    let svg_tree = Tree::from_str(&template, &options).unwrap();
-
-    let size = svg_tree.size();
-    let (width, height) = (size.width() as usize, size.height() as usize);
+    let xml = usvg::roxmltree::Document::parse_with_options(
+        &template,
+        usvg::roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        },
+    )
+    .map_err(|e| Error::Render(format!("the svg: {e}")))?;
+    let options = usvg::Options {
+        fontdb: std::sync::Arc::new(fontdb),
+        dpi: svg_dpi(&xml, device),
+        ..Default::default()
+    };
 
-    let mut image: Vec<u8> = vec![0; width * height * BYTES_PER_PIXEL];
+    let svg_tree =
+        Tree::from_xmltree(&xml, &options).map_err(|e| Error::Render(format!("the svg: {e}")))?;
 
     info!("Rendering the svg...");
     let now = Instant::now();
-    resvg::render(
-        &svg_tree,
-        Transform::identity(),
-        &mut PixmapMut::from_bytes(&mut image, size.width() as u32, size.height() as u32).unwrap(),
-    );
+    let rendered = rasterize(&svg_tree, device)?;
     let elapsed = format!("{:.2?}", now.elapsed());
     info!("Rendering took {elapsed}");
 
-    let image_vec = image.to_vec();
-    let img_buffer: ImageBuffer<Rgba<u8>, Vec<u8>> =
-        ImageBuffer::from_raw(width as u32, height as u32, image_vec).unwrap();
-    let result = DynamicImage::ImageRgba8(img_buffer);
-    return result;
-}
-
-async fn clear_screen() {
-    Command::new("eips")
-        .arg("-d")
-        .arg("l=0,w=9999,h=9999")
-        .output()
-        .ok();
-    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
-    Command::new("eips").arg("-c").output().ok();
-    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
-    Command::new("eips")
-        .arg("-d")
-        .arg("l=0,w=9999,h=9999")
-        .output()
-        .ok();
-    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
-}
-
-pub async fn update_screen(path: String) -> Result<std::process::Output, std::io::Error> {
-    clear_screen().await;
-    Command::new("eips").arg("-g").arg(path).output()
+    Ok(rendered)
 }
 
-pub async fn show_panic(panic: &String) -> Result<(), Box<dyn std::error::Error>> {
+/// Last resort when the dashboard itself cannot be rendered, prints the error with eips
+pub async fn show_error(error: &Error) -> Result<(), Error> {
     // As minimal as possible to avoid any "dangerous" code
     if std::env::var("NOT_KINDLE").is_err() {
         tokio::time::sleep(tokio::time::Duration::from_millis(2000)).await;
@@ -502,100 +497,445 @@ pub async fn show_panic(panic: &String) -> Result<(), Box<dyn std::error::Error>
         let output = Command::new("eips")
             .arg("2")
             .arg("1")
-            .arg(format!("\"{panic}\""))
+            .arg(format!("\"{error}\""))
             .output();
         match output {
             Ok(_) => Ok(()),
-            Err(e) => Err(format!("Could not show error: {e}").into()),
+            Err(e) => Err(Error::Display(format!("Could not show error: {e}"))),
         }
     } else {
-        info!("Skipping showing the panic due to env NOT_KINDLE");
+        info!("Skipping showing the error due to env NOT_KINDLE");
         Ok(())
     }
 }
 
-pub fn save(mut image: DynamicImage) -> String {
-    let output_path = "output.png".to_string();
-
-    info!("Saving the rendering...");
-    let now = Instant::now();
+/// Renders `page` as of `now` and shows it, if anything changed
+pub async fn render_png(
+    config: &Config,
+    device: &Device,
+    data: &KindleDisplayData,
+    page: &PageConfig,
+    now: &DateTime<Tz>,
+    display: &mut dyn Display,
+    refresher: &mut Refresher,
+) -> Result<(), Error> {
+    let start = Instant::now();
 
-    let screen = get_screen_dim().unwrap_or_else(|| {
-        warn!("Could not determine screen size, switching to 600x800");
-        Screen {
-            width: 600,
-            height: 800,
+    info!("Rendering page {}", page.name);
+    let template = create_output_svg(config, data, page, now)?;
+    if let Some(path) = &config.display.svg {
+        match std::fs::write(path, &template) {
+            Ok(()) => info!("Saved {}", path.display()),
+            Err(e) => warn!("Could not save {}: {e}", path.display()),
         }
-    });
-
-    image = image.resize_exact(
-        screen.height,
-        screen.width,
-        image::imageops::FilterType::Lanczos3,
+    }
+    let (mut image, photos) = render_svg(template, device).await?;
+    dither::quantize(
+        &mut image,
+        config.display.gray_levels.unwrap_or(device.gray_levels),
+        &photos,
+        config.display.dither,
     );
-    if env::var("NOT_KINDLE").is_err() {
-        image = image.rotate90();
+
+    match refresher.plan(&image) {
+        None => info!("Nothing changed, leaving the screen as is"),
+        Some(update) => {
+            match &update {
+                Update::Full => info!("Refreshing the whole screen"),
+                Update::Partial(rects) => info!("Refreshing {} changed regions", rects.len()),
+            }
+            display.show(&image, &update).await?;
+            refresher.shown(image, &update);
+            info!("Success! Now showing the result!");
+        }
     }
-    let result: image::GrayImage = DynamicImage::ImageRgb8(image.into()).into_luma8();
-    result.save(output_path.clone()).unwrap();
-    let elapsed = format!("{:.2?}", now.elapsed());
-    info!("Saving took {elapsed} {output_path}");
-    output_path
+
+    let elapsed = format!("{:.2?}", start.elapsed());
+    info!("Finished in {elapsed}");
+    Ok(())
 }
 
-pub async fn render_png() {
-    let start = Instant::now();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::Battery;
+    use crate::display::PngFile;
+    use crate::radar::Wind;
+    use crate::stats::tides::{TideEntry, TideTable};
+    use crate::weather::{Forecast, HourData};
+    use chrono::{NaiveDate, NaiveTime, TimeZone, Utc};
+    use image::{Rgb, RgbImage};
+    use std::path::Path;
+
+    #[test]
+    fn test_template_without_data() {
+        let data = KindleDisplayData::default();
+        let assets = Assets::new("no-such-dir");
+        let svg = fill_template(
+            assets::embedded(assets::TEMPLATE).unwrap(),
+            &data,
+            &Config::default(),
+            &PageConfig::default(),
+            &Utc::now().with_timezone(&Tz::UTC),
+            &assets,
+        )
+        .unwrap();
+
+        assert!(svg.contains(">ERR</text>"));
+        assert!(Tree::from_str(&svg, &usvg::Options::default()).is_ok());
+    }
 
-    let template = create_output_svg().await;
-    let image = render_svg(template).await;
-    let output_pth = save(image.clone());
-    let eips_result = update_screen(output_pth).await;
+    struct Broken;
 
-    match eips_result {
-        Ok(_r) => {
-            info!("Success! Now showing the result!")
+    #[async_trait::async_trait]
+    impl crate::source::DataSource for Broken {
+        type Output = u32;
+        const NAME: &'static str = "broken";
+
+        fn refresh(&self) -> stdDuration {
+            stdDuration::ZERO
+        }
+
+        async fn fetch(&self) -> Result<Self::Output, Error> {
+            Err(Error::Parse("json: expected value".to_string()))
         }
-        Err(e) => warn!("Could not show result! Is eips available? {e}"), // Mainly for testing
     }
 
-    let elapsed = format!("{:.2?}", start.elapsed());
-    info!("Finished in {elapsed}");
-}
+    #[tokio::test]
+    async fn test_error_banner() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut registry = crate::source::Registry::new(crate::cache::Cache::new(temp.path()));
+        registry.register(Broken);
+        let mut data = registry.fetch_all().await;
+        let mut config = Config::default();
+        let now = Utc::now().with_timezone(&config.timezone);
+        let assets = Assets::new("no-such-dir");
+        let banner = |data: &KindleDisplayData, config: &Config| {
+            fill_template(
+                "<svg>{% for e in errors %}[{{ e }}]{% endfor %}</svg>",
+                data,
+                config,
+                &PageConfig::default(),
+                &now,
+                &assets,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            banner(&data, &config),
+            "<svg>[broken: unexpected data]</svg>"
+        );
+
+        data.offline = true;
+        config.cache.min_free_mb = u64::MAX / (1024 * 1024);
+        let svg = banner(&data, &config);
+        assert!(svg.starts_with(
+            "<svg>[Offline, showing cached data][broken: unexpected data][Low disk space"
+        ));
+
+        // The built-in template fits it in as well
+        let svg = fill_template(
+            assets::embedded(assets::TEMPLATE).unwrap(),
+            &data,
+            &config,
+            &PageConfig::default(),
+            &now,
+            &assets,
+        )
+        .unwrap();
+        assert!(svg.contains("Offline, showing cached data · broken: unexpected data"));
+    }
 
-pub fn replace_image(template: String, href: &str, tag_replacement: &str) -> String {
-    // Escape the href to safely insert it in a regex
-    let href_pattern = regex::escape(href);
+    #[test]
+    fn test_custom_template() {
+        let data = KindleDisplayData::default();
+        let assets = Assets::new("no-such-dir");
+        let config = Config::default();
+        let now = Utc::now().with_timezone(&config.timezone);
+        let fill =
+            |src: &str, page: &PageConfig| fill_template(src, &data, &config, page, &now, &assets);
+
+        // Leaving widgets out is fine, misspelling them is not
+        let page = PageConfig::default();
+        let svg = fill("<svg>{{ wind | default(\"NA\") }}</svg>", &page);
+        assert_eq!(svg.unwrap(), "<svg>NA</svg>");
+        assert!(fill("<svg>{{ wnd }}</svg>", &page).is_err());
+
+        // Sources the page does not list are there, but missing
+        let page = PageConfig {
+            sources: Some(vec!["moon".to_string()]),
+            ..Default::default()
+        };
+        assert!(fill("<svg>{{ moon }}{{ time.hour }}</svg>", &page).is_ok());
+        let svg = fill("<svg>{% if wind %}wind{% else %}NA{% endif %}</svg>", &page);
+        assert_eq!(svg.unwrap(), "<svg>NA</svg>");
+    }
 
-    // Regex to match <image ... href="exact match" ... />
-    let pattern = format!(r#"<image\b[^>]*?\bhref\s*=\s*"{}"[^>]*/?>"#, href_pattern);
-    let re = Regex::new(&pattern).unwrap();
+    #[test]
+    fn test_page_with_fewer_sources() {
+        let data = full_data();
+        let mut config = Config::default();
+        config.assets.dir = "no-such-dir".into();
+        let now = Utc::now().with_timezone(&config.timezone);
+        let page = PageConfig {
+            sources: Some(vec!["tides".to_string(), "moon".to_string()]),
+            ..Default::default()
+        };
+
+        // The built-in template only draws what the page lists
+        let svg = create_output_svg(&config, &data, &page, &now).unwrap();
+        assert!(svg.contains("Pleamar"));
+        assert!(!svg.contains("7.5 m/s"));
+        assert!(!svg.contains(r#"id="hourly""#));
+        assert!(Tree::from_str(&svg, &usvg::Options::default()).is_ok());
+    }
 
-    re.replace_all(&template, tag_replacement).to_string()
-}
+    #[test]
+    fn test_svg_dpi() {
+        // An inch of svg is an inch of panel, the 1200 wide dashboard is 1448 pixels there
+        let paperwhite3 = crate::device::resolve("pw3");
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="1200" height="900" viewBox="0 0 1200 900"/>"#;
+        let xml = usvg::roxmltree::Document::parse(svg).unwrap();
+        let inch = svg_dpi(&xml, &paperwhite3) * 1448.0 / 1200.0;
+        assert!((inch - 300.0).abs() < 0.01);
+
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="800px" height="600px"/>"#;
+        let xml = usvg::roxmltree::Document::parse(svg).unwrap();
+        let desktop = crate::device::resolve("desktop");
+        assert_eq!(svg_dpi(&xml, &desktop), 96.0);
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_rasterize_rotated() {
+        // Landscape svg with a black square in its top left corner
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="40" height="30">
+            <rect width="40" height="30" fill="white"/>
+            <rect width="10" height="10" fill="black"/>
+        </svg>"#;
+        let tree = Tree::from_str(svg, &usvg::Options::default()).unwrap();
+        let mut device = Device {
+            name: "Test",
+            width: 60,
+            height: 80,
+            dpi: 96,
+            gray_levels: 16,
+            rotate: false,
+            partial_refresh: true,
+            reagl: true,
+            framebuffer: false,
+        };
+
+        let (image, _) = rasterize(&tree, &device).unwrap();
+        assert_eq!(image.dimensions(), (80, 60));
+        assert_eq!(image.get_pixel(5, 5).0[0], 0);
+
+        // Turned clockwise onto the portrait panel, the corner ends up top right
+        device.rotate = true;
+        let (image, _) = rasterize(&tree, &device).unwrap();
+        assert_eq!(image.dimensions(), (60, 80));
+        assert_eq!(image.get_pixel(55, 5).0[0], 0);
+        assert_eq!(image.get_pixel(5, 5).0[0], 255);
+        assert_eq!(image.get_pixel(5, 75).0[0], 255);
+    }
 
     #[test]
-    fn test_replace_image_exact_href() {
-        let input = r#"
-            <svg>
-                <image
-                    href="./moon/1.svg"
-                    id="quirky"
-                />
-            </svg>
-        "#;
-
-        let expected = r#"
-            <svg>
-                <path />
-            </svg>
-        "#;
-
-        let output = replace_image(input.to_string(), "./moon/1.svg", r#"<path />"#);
-
-        assert_eq!(output.trim(), expected.trim());
+    fn test_age_badge() {
+        assert_eq!(age_badge(None), "");
+        assert_eq!(age_badge(Some(stdDuration::from_secs(25 * 60))), "25m ago");
+        assert_eq!(
+            age_badge(Some(stdDuration::from_secs(3 * 60 * 60 + 59))),
+            "3h ago"
+        );
+        assert_eq!(
+            age_badge(Some(stdDuration::from_secs(3 * 24 * 60 * 60))),
+            "3d ago"
+        );
+    }
+
+    // Whole frames the pipeline is expected to produce. After a change to the layout that
+    // is meant to be, run the tests with UPDATE_GOLDEN=1 and check the new pngs in
+    const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/golden");
+
+    // Antialiasing may come out a little different elsewhere, a gray level or so
+    const PIXEL_TOLERANCE: u8 = 24;
+    // Share of the pixels that may be further off than that, less than a changed digit
+    const MAX_DIFFERENT: f64 = 0.0001;
+
+    fn tide(time: &str, high: bool) -> TideEntry {
+        let tide = if high {
+            Tide::High(time.to_string())
+        } else {
+            Tide::Low(time.to_string())
+        };
+        TideEntry {
+            time: NaiveTime::parse_from_str(time, "%H:%M").unwrap(),
+            tide,
+        }
+    }
+
+    fn day(date: u32, rain_sum: f64, cloud_sum: f64, max_c: f64, min_c: f64) -> DayData {
+        DayData {
+            data_points: 8,
+            date: NaiveDate::from_ymd_opt(2024, 6, date).unwrap(),
+            rain_sum,
+            cloud_sum,
+            max_c,
+            min_c,
+        }
+    }
+
+    // 3 hourly points through the fixed now, warm in the afternoon and a wet Sunday
+    fn hours() -> Vec<HourData> {
+        let start = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        (0..24)
+            .map(|i| {
+                let time = start + chrono::Duration::hours(3 * i);
+                let hour = time.hour() as f64;
+                let rain_mm = if (14..20).contains(&i) {
+                    (i - 13) as f64 * 0.8
+                } else {
+                    0.0
+                };
+                HourData {
+                    time,
+                    temp_c: 20.0 + 4.0 * ((hour - 9.0) / 24.0 * std::f64::consts::TAU).sin(),
+                    rain_mm,
+                }
+            })
+            .collect()
+    }
+
+    // Every widget filled in, with a map that has gradients to dither
+    fn full_data() -> KindleDisplayData {
+        let mut data = KindleDisplayData::default();
+        data.insert::<WeatherSource>(Forecast {
+            days: vec![
+                day(1, 0.0, 120.0, 24.5, 18.2),
+                day(2, 2.0, 480.0, 22.0, 17.6),
+                day(3, 16.0, 720.0, 19.8, 16.1),
+            ],
+            hours: hours(),
+        });
+        data.insert::<TidesSource>(TideTable {
+            date: NaiveDate::from_ymd_opt(2024, 6, 1).unwrap(),
+            entries: vec![
+                tide("04:12", true),
+                tide("10:31", false),
+                tide("16:40", true),
+                tide("22:55", false),
+            ],
+        });
+        data.insert::<MoonSource>(0.25);
+        let map = RgbImage::from_fn(400, 300, |x, y| {
+            Rgb([(x * 255 / 400) as u8, (y * 255 / 300) as u8, 160])
+        });
+        data.insert::<RadarSource>(RadarMap {
+            image: DynamicImage::ImageRgb8(map),
+        });
+        data.insert::<WindSource>(Wind {
+            speed: 7.5,
+            direction: 45.0,
+        });
+        data.insert::<BatterySource>(Battery {
+            percent: 15,
+            charging: false,
+        });
+        data
+    }
+
+    // Through the whole pipeline, up to the png a desktop run saves
+    async fn render(name: &str, data: &KindleDisplayData) -> GrayImage {
+        let mut config = Config::default();
+        config.assets.dir = "no-such-dir".into();
+        // Not at the mercy of the disk the tests run on
+        config.cache.min_free_mb = 0;
+        let now = config
+            .timezone
+            .with_ymd_and_hms(2024, 6, 1, 10, 15, 0)
+            .unwrap();
+
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(format!("{name}.png"));
+        let mut display = PngFile::new(&path);
+        render_png(
+            &config,
+            &crate::device::resolve("desktop"),
+            data,
+            &PageConfig::default(),
+            &now,
+            &mut display,
+            &mut Refresher::new(1),
+        )
+        .await
+        .unwrap();
+
+        image::open(&path).unwrap().into_luma8()
+    }
+
+    // Compares `actual` to the golden png `name`, saving it and an image of where they
+    // differ, in red, when they do
+    fn check_golden(name: &str, actual: &GrayImage) {
+        let path = Path::new(GOLDEN_DIR).join(format!("{name}.png"));
+        if std::env::var("UPDATE_GOLDEN").is_ok() {
+            std::fs::create_dir_all(GOLDEN_DIR).unwrap();
+            actual.save(&path).unwrap();
+            return;
+        }
+
+        let golden = match image::open(&path) {
+            Ok(golden) => golden.into_luma8(),
+            Err(e) => panic!("No {}: {e}, run with UPDATE_GOLDEN=1", path.display()),
+        };
+        assert_eq!(
+            golden.dimensions(),
+            actual.dimensions(),
+            "{name} changed size"
+        );
+
+        let (width, height) = actual.dimensions();
+        let mut diff = RgbImage::new(width, height);
+        let mut different = 0;
+        for ((x, y, pixel), expected) in actual.enumerate_pixels().zip(golden.pixels()) {
+            let gray = pixel.0[0];
+            if gray.abs_diff(expected.0[0]) > PIXEL_TOLERANCE {
+                different += 1;
+                diff.put_pixel(x, y, Rgb([255, 0, 0]));
+            } else {
+                // Faded, so the red stands out
+                let faded = 192 + gray / 4;
+                diff.put_pixel(x, y, Rgb([faded, faded, faded]));
+            }
+        }
+
+        let share = different as f64 / (width * height) as f64;
+        if share > MAX_DIFFERENT {
+            let dir = std::env::temp_dir().join("kindle-bueno-golden");
+            std::fs::create_dir_all(&dir).unwrap();
+            let actual_path = dir.join(format!("{name}.png"));
+            let diff_path = dir.join(format!("{name}.diff.png"));
+            actual.save(&actual_path).unwrap();
+            diff.save(&diff_path).unwrap();
+            panic!(
+                "{name} differs from its golden in {different} pixels ({:.2}%), see {} and {}",
+                share * 100.0,
+                actual_path.display(),
+                diff_path.display()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_golden_dashboard() {
+        let image = render("dashboard", &full_data()).await;
+        check_golden("dashboard", &image);
+    }
+
+    #[tokio::test]
+    async fn test_golden_offline_without_data() {
+        let mut data = KindleDisplayData::default();
+        data.offline = true;
+        let image = render("offline", &data).await;
+        check_golden("offline", &image);
     }
 }
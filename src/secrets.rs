@@ -0,0 +1,154 @@
+use serde::Deserialize;
+
+use crate::error::Error;
+
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+// Where each key lived before there was a secrets file, still read if it is missing there
+const LEGACY_FILES: [(&str, &str); 2] = [
+    ("openweathermap", "sensitive/openweatherkey.json"),
+    ("aemet", "sensitive/aemet.json"),
+];
+
+/// An api key or password. Prints as `***`, so it cannot end up in a log by accident
+#[derive(Clone)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("***")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("***")
+    }
+}
+
+#[derive(Deserialize)]
+struct LegacyKey {
+    key: String,
+}
+
+/*
+    The credentials sources need, by name, see `DataSource::CREDENTIALS`. Read from one
+    toml file of `name = "value"` lines, the environment variable `env_var(name)`
+    overrides a value in the file.
+*/
+#[derive(Debug)]
+pub struct Secrets {
+    values: HashMap<String, Secret>,
+    // Looks up an environment variable, swapped out in tests
+    env: fn(&str) -> Option<String>,
+}
+
+impl Default for Secrets {
+    fn default() -> Self {
+        Secrets {
+            values: HashMap::new(),
+            env: |var| std::env::var(var).ok(),
+        }
+    }
+}
+
+impl Secrets {
+    /// Reads `path`, which may not exist when every key comes from the environment
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let mut secrets = if path.exists() {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| Error::Config(format!("Could not read {}: {e}", path.display())))?;
+            let secrets = Secrets::parse(&contents)
+                .map_err(|e| Error::Config(format!("Invalid {}: {e}", path.display())))?;
+            info!(
+                "Loaded {} secrets from {}",
+                secrets.values.len(),
+                path.display()
+            );
+            secrets
+        } else {
+            Secrets::default()
+        };
+
+        for (name, legacy) in LEGACY_FILES {
+            if secrets.values.contains_key(name) {
+                continue;
+            }
+            let Ok(contents) = std::fs::read_to_string(legacy) else {
+                continue;
+            };
+            match serde_json::from_str::<LegacyKey>(&contents) {
+                Ok(LegacyKey { key }) => {
+                    warn!(
+                        "Read the {name} key from {legacy}, move it to {}",
+                        path.display()
+                    );
+                    secrets.values.insert(name.to_string(), Secret(key));
+                }
+                Err(e) => warn!("Ignoring unreadable {legacy}: {e}"),
+            }
+        }
+        Ok(secrets)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        // No toml error, it quotes the line it failed on
+        let values: HashMap<String, String> =
+            toml::from_str(contents).map_err(|_| "expected name = \"value\" lines".to_string())?;
+        let values = values
+            .into_iter()
+            .map(|(name, value)| (name, Secret(value)))
+            .collect();
+        Ok(Secrets {
+            values,
+            ..Default::default()
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<Secret> {
+        match (self.env)(&env_var(name)) {
+            Some(value) if !value.is_empty() => Some(Secret(value)),
+            _ => self.values.get(name).cloned(),
+        }
+    }
+}
+
+/// The environment variable for secret `name`, like `KINDLE_BUENO_AEMET_KEY`
+pub fn env_var(name: &str) -> String {
+    format!("KINDLE_BUENO_{}_KEY", name.to_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secrets() {
+        let secrets = Secrets::parse("test_file = \"0123abcd\"\n").unwrap();
+        assert_eq!(secrets.get("test_file").unwrap().expose(), "0123abcd");
+        assert!(secrets.get("test_missing").is_none());
+
+        // Never shows up in logs or errors
+        let secret = secrets.get("test_file").unwrap();
+        assert!(!format!("{secret} {secret:?} {secrets:?}").contains("0123abcd"));
+        let e = Secrets::parse("test_file = 0123abcd").err().unwrap();
+        assert!(!e.contains("0123abcd"));
+
+        // The environment wins
+        let secrets = Secrets {
+            env: |var| (var == "KINDLE_BUENO_TEST_ENV_KEY").then(|| "from-env".to_string()),
+            ..Secrets::parse("test_env = \"from-file\"\ntest_file = \"0123abcd\"").unwrap()
+        };
+        assert_eq!(secrets.get("test_env").unwrap().expose(), "from-env");
+        assert_eq!(secrets.get("test_file").unwrap().expose(), "0123abcd");
+    }
+}
@@ -0,0 +1,585 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::battery::BatterySource;
+use crate::cache::Cache;
+use crate::config::Config;
+use crate::error::Error;
+use crate::http::Http;
+use crate::radar::{RadarSource, WindSource};
+use crate::secrets::{self, Secret, Secrets};
+use crate::stats::tides::TidesSource;
+use crate::stats::MoonSource;
+use crate::weather::WeatherSource;
+
+use log::{info, warn};
+
+use async_std::future;
+use futures::future::join_all;
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Every source that can be enabled in the config, in the order they are fetched.
+pub const SOURCE_NAMES: [&str; 6] = [
+    WeatherSource::NAME,
+    TidesSource::NAME,
+    MoonSource::NAME,
+    RadarSource::NAME,
+    WindSource::NAME,
+    BatterySource::NAME,
+];
+
+// Sources are fetched once per cycle at most, so one that is due a few seconds
+// after the cycle starts should not have to wait a whole extra cycle.
+const REFRESH_SLACK: Duration = Duration::from_secs(60);
+
+/*
+    A widget's data, fetched from wherever it comes from.
+
+    Sources are registered in a `Registry`, which fetches all of them concurrently with a
+    timeout, and hands the renderer a `KindleDisplayData` to look results up by source type.
+    Outputs are serializable so the registry can keep them in the on-disk cache.
+*/
+#[async_trait]
+pub trait DataSource: Send + Sync + 'static {
+    type Output: Serialize + DeserializeOwned + Send + Sync + 'static;
+
+    /// Unique name, used in the config, the cache and in logs
+    const NAME: &'static str;
+
+    /// Names of the secrets it needs, it is only enabled when all of them are set
+    const CREDENTIALS: &'static [&'static str] = &[];
+
+    // Room for the http client's retries
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(60)
+    }
+
+    /// How long a successful result is good for before fetching again, unless
+    /// overridden by `cache.ttl_minutes` in the config
+    fn refresh(&self) -> Duration;
+
+    /// Whether it is set up to fetch, without going online. Its credentials are checked
+    /// before it is even registered
+    fn check(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Whether an earlier output still applies, whatever its age. One that does not is
+    /// never shown, not even when fetching again fails
+    fn is_current(&self, _output: &Self::Output) -> bool {
+        true
+    }
+
+    async fn fetch(&self) -> Result<Self::Output, Error>;
+}
+
+type AnyOutput = Arc<dyn Any + Send + Sync>;
+
+#[derive(Clone)]
+struct Fetched {
+    at: DateTime<Utc>,
+    value: AnyOutput,
+}
+
+impl Fetched {
+    fn age(&self) -> Duration {
+        (Utc::now() - self.at).to_std().unwrap_or_default()
+    }
+}
+
+// Object safe version of `DataSource`, so the registry can hold any of them.
+#[async_trait]
+trait AnySource: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn timeout(&self) -> Duration;
+    fn refresh(&self) -> Duration;
+    fn check(&self) -> Result<(), Error>;
+    fn is_current(&self, value: &AnyOutput) -> bool;
+    async fn fetch_any(&self) -> Result<AnyOutput, Error>;
+    fn load(&self, cache: &Cache) -> Option<Fetched>;
+    fn store(&self, cache: &Cache, fetched: &Fetched) -> Result<(), Error>;
+    fn to_json(&self, value: &AnyOutput) -> Result<serde_json::Value, Error>;
+}
+
+#[async_trait]
+impl<S: DataSource> AnySource for S {
+    fn name(&self) -> &'static str {
+        S::NAME
+    }
+
+    fn timeout(&self) -> Duration {
+        DataSource::timeout(self)
+    }
+
+    fn refresh(&self) -> Duration {
+        DataSource::refresh(self)
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        DataSource::check(self)
+    }
+
+    fn is_current(&self, value: &AnyOutput) -> bool {
+        downcast::<S>(value).is_ok_and(|output| DataSource::is_current(self, output))
+    }
+
+    async fn fetch_any(&self) -> Result<AnyOutput, Error> {
+        let output = self.fetch().await?;
+        Ok(Arc::new(output))
+    }
+
+    fn load(&self, cache: &Cache) -> Option<Fetched> {
+        let (at, value) = cache.load::<S::Output>(S::NAME)?;
+        Some(Fetched {
+            at,
+            value: Arc::new(value),
+        })
+    }
+
+    fn store(&self, cache: &Cache, fetched: &Fetched) -> Result<(), Error> {
+        cache.store(S::NAME, fetched.at, downcast::<S>(&fetched.value)?)
+    }
+
+    fn to_json(&self, value: &AnyOutput) -> Result<serde_json::Value, Error> {
+        Ok(serde_json::to_value(downcast::<S>(value)?)?)
+    }
+}
+
+fn downcast<S: DataSource>(value: &AnyOutput) -> Result<&S::Output, Error> {
+    value
+        .downcast_ref::<S::Output>()
+        .ok_or_else(|| Error::Parse(format!("{}, its output has an unexpected type", S::NAME)))
+}
+
+pub struct Registry {
+    sources: Vec<Box<dyn AnySource>>,
+    ttl: HashMap<String, Duration>,
+    cache: Cache,
+    /// Enabled in the config but missing credentials, with the missing ones
+    disabled: Vec<(&'static str, Vec<&'static str>)>,
+    // Decoded last good results, so the cache files are only read on startup
+    last: Mutex<HashMap<&'static str, Fetched>>,
+}
+
+impl Registry {
+    pub fn new(cache: Cache) -> Self {
+        Registry {
+            sources: vec![],
+            ttl: HashMap::new(),
+            cache,
+            disabled: vec![],
+            last: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Registers every source enabled in the config that has its credentials, fetching
+    /// through `http`
+    pub fn from_config(config: &Config, http: &Http, secrets: &Secrets) -> Self {
+        let mut registry = Registry::new(Cache::new(&config.cache.dir));
+        registry.ttl = config
+            .cache
+            .ttl_minutes
+            .iter()
+            .map(|(name, minutes)| (name.clone(), Duration::from_secs(minutes * 60)))
+            .collect();
+
+        for name in &config.sources {
+            match name.as_str() {
+                WeatherSource::NAME => {
+                    registry.enable(secrets, |keys| WeatherSource::new(config, http, &keys[0]))
+                }
+                TidesSource::NAME => registry.register(TidesSource::new(config, http)),
+                MoonSource::NAME => registry.register(MoonSource::new(config)),
+                RadarSource::NAME => registry.register(RadarSource::new(http)),
+                WindSource::NAME => {
+                    registry.enable(secrets, |keys| WindSource::new(config, http, &keys[0]))
+                }
+                BatterySource::NAME => registry.register(BatterySource::new(config)),
+                _ => warn!("Unknown source {name}, skipping"),
+            }
+        }
+
+        let active: Vec<_> = registry.sources.iter().map(|s| s.name()).collect();
+        info!("Active sources: {}", active.join(", "));
+        for (name, missing) in &registry.disabled {
+            warn!("{name} is disabled: {}", missing_credentials(missing));
+        }
+        registry
+    }
+
+    pub fn register<S: DataSource>(&mut self, source: S) {
+        self.sources.push(Box::new(source));
+    }
+
+    /// Registers the source `make` builds from its credentials, if they are all set
+    fn enable<S: DataSource>(&mut self, secrets: &Secrets, make: impl FnOnce(&[Secret]) -> S) {
+        let keys: Vec<_> = S::CREDENTIALS
+            .iter()
+            .map(|name| secrets.get(name))
+            .collect();
+        let missing: Vec<_> = S::CREDENTIALS
+            .iter()
+            .zip(&keys)
+            .filter(|(_, key)| key.is_none())
+            .map(|(name, _)| *name)
+            .collect();
+
+        if missing.is_empty() {
+            let keys: Vec<_> = keys.into_iter().flatten().collect();
+            self.register(make(&keys));
+        } else {
+            self.disabled.push((S::NAME, missing));
+        }
+    }
+
+    /// Fetches every source that is due, reusing the previous result for the rest.
+    pub async fn fetch_all(&self) -> KindleDisplayData {
+        info!("Fetching all data...");
+        let now = Instant::now();
+
+        let fetches = self
+            .sources
+            .iter()
+            .map(|source| self.fetch_one(source.as_ref()));
+        let results = join_all(fetches).await;
+
+        let elapsed = format!("{:.2?}", now.elapsed());
+        info!("Fetched all kindle data in {elapsed}");
+
+        KindleDisplayData {
+            results: results.into_iter().collect(),
+            offline: false,
+        }
+    }
+
+    /// Whether every enabled source is set up, without fetching any
+    pub fn check(&self) -> Vec<(&'static str, Result<(), Error>)> {
+        let registered = self
+            .sources
+            .iter()
+            .map(|source| (source.name(), source.check()));
+        let disabled = self.disabled.iter().map(|(name, missing)| {
+            let e = Error::Config(missing_credentials(missing));
+            (*name, Err(e))
+        });
+        registered.chain(disabled).collect()
+    }
+
+    /// Fetches source `name` now, whatever is cached, as json
+    pub async fn fetch_json(&self, name: &str) -> Result<serde_json::Value, Error> {
+        if let Some((_, missing)) = self.disabled.iter().find(|(n, _)| *n == name) {
+            return Err(Error::Config(missing_credentials(missing)));
+        }
+        let Some(source) = self.sources.iter().find(|source| source.name() == name) else {
+            return Err(Error::Config(format!(
+                "no source {name}, it is one of {}",
+                SOURCE_NAMES.join(", ")
+            )));
+        };
+        let value = fetch_with_timeout(source.as_ref()).await?;
+        source.to_json(&value)
+    }
+
+    fn previous(&self, source: &dyn AnySource) -> Option<Fetched> {
+        let name = source.name();
+        let mut last = self.last.lock().unwrap();
+        if !last.contains_key(name) {
+            let fetched = source.load(&self.cache)?;
+            last.insert(name, fetched);
+        }
+        last.get(name).cloned()
+    }
+
+    async fn fetch_one(&self, source: &dyn AnySource) -> (&'static str, SourceResult) {
+        let name = source.name();
+        let ttl = self.ttl.get(name).copied().unwrap_or(source.refresh());
+        let (value, error) = self.fetch_or_reuse(source, ttl).await;
+        (name, SourceResult { value, error, ttl })
+    }
+
+    async fn fetch_or_reuse(
+        &self,
+        source: &dyn AnySource,
+        ttl: Duration,
+    ) -> (Option<Fetched>, Option<Error>) {
+        let name = source.name();
+        let previous = self.previous(source).filter(|previous| {
+            let current = source.is_current(&previous.value);
+            if !current {
+                info!("Dropping the cached {name}, it no longer applies");
+            }
+            current
+        });
+
+        if let Some(previous) = &previous {
+            if previous.age() + REFRESH_SLACK < ttl {
+                info!("Reusing {name}, fetched {:.0?} ago", previous.age());
+                return (Some(previous.clone()), None);
+            }
+        }
+
+        info!("Fetching {name}...");
+        let now = Instant::now();
+
+        let result = fetch_with_timeout(source).await;
+
+        let elapsed = format!("{:.2?}", now.elapsed());
+        match result {
+            Ok(value) => {
+                info!("{name} took {elapsed}");
+                let fetched = Fetched {
+                    at: Utc::now(),
+                    value,
+                };
+                if let Err(e) = source.store(&self.cache, &fetched) {
+                    warn!("Could not cache {name}: {e}");
+                }
+                self.last.lock().unwrap().insert(name, fetched.clone());
+                (Some(fetched), None)
+            }
+            Err(e) => {
+                warn!("{name} failed after {elapsed}: {e}");
+                if let Some(previous) = &previous {
+                    warn!(
+                        "Serving {name} from cache, fetched {:.0?} ago",
+                        previous.age()
+                    );
+                }
+                (previous, Some(e))
+            }
+        }
+    }
+}
+
+fn missing_credentials(missing: &[&str]) -> String {
+    let names: Vec<_> = missing
+        .iter()
+        .map(|name| format!("{name} (or {})", secrets::env_var(name)))
+        .collect();
+    format!("no {} in the secrets file", names.join(", "))
+}
+
+async fn fetch_with_timeout(source: &dyn AnySource) -> Result<AnyOutput, Error> {
+    match future::timeout(source.timeout(), source.fetch_any()).await {
+        Ok(r) => r,
+        Err(_) => Err(Error::Network(format!(
+            "Timeout after {:.0?}",
+            source.timeout()
+        ))),
+    }
+}
+
+struct SourceResult {
+    /// Fetched now or served from the cache
+    value: Option<Fetched>,
+    /// Why fetching it failed this time, even if the cache stood in for it
+    error: Option<Error>,
+    ttl: Duration,
+}
+
+/// Results of one fetch of every registered source
+#[derive(Default)]
+pub struct KindleDisplayData {
+    results: HashMap<&'static str, SourceResult>,
+    /// Fetched without a connection, so whatever is shown comes from the cache
+    pub offline: bool,
+}
+
+impl KindleDisplayData {
+    fn fetched<S: DataSource>(&self) -> Option<&Fetched> {
+        self.results.get(S::NAME)?.value.as_ref()
+    }
+
+    /// The output of source `S`, if it is registered and it was fetched now or earlier
+    pub fn get<S: DataSource>(&self) -> Option<&S::Output> {
+        self.fetched::<S>()?.value.downcast_ref::<S::Output>()
+    }
+
+    /// Sources that failed to fetch this time, whether or not the cache stood in
+    pub fn errors(&self) -> impl Iterator<Item = (&'static str, &Error)> {
+        self.results
+            .iter()
+            .filter_map(|(name, result)| Some((*name, result.error.as_ref()?)))
+    }
+
+    /// Age of the output of source `S`, only if it is older than its refresh interval
+    pub fn stale_age<S: DataSource>(&self) -> Option<Duration> {
+        let age = self.fetched::<S>()?.age();
+        if age > self.results.get(S::NAME)?.ttl + REFRESH_SLACK {
+            Some(age)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+impl KindleDisplayData {
+    /// `value` as the freshly fetched output of source `S`, to render fixed data
+    pub fn insert<S: DataSource>(&mut self, value: S::Output) {
+        let fetched = Fetched {
+            at: Utc::now(),
+            value: Arc::new(value),
+        };
+        let result = SourceResult {
+            value: Some(fetched),
+            error: None,
+            ttl: Duration::from_secs(60 * 60),
+        };
+        self.results.insert(S::NAME, result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Constant;
+
+    #[async_trait]
+    impl DataSource for Constant {
+        type Output = u32;
+        const NAME: &'static str = "constant";
+
+        fn refresh(&self) -> Duration {
+            Duration::from_secs(60 * 60)
+        }
+
+        async fn fetch(&self) -> Result<Self::Output, Error> {
+            Ok(42)
+        }
+    }
+
+    struct Slow;
+
+    #[async_trait]
+    impl DataSource for Slow {
+        type Output = u32;
+        const NAME: &'static str = "slow";
+
+        fn timeout(&self) -> Duration {
+            Duration::from_millis(10)
+        }
+
+        fn refresh(&self) -> Duration {
+            Duration::ZERO
+        }
+
+        fn check(&self) -> Result<(), Error> {
+            Err(Error::Config("no key".to_string()))
+        }
+
+        async fn fetch(&self) -> Result<Self::Output, Error> {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Ok(1)
+        }
+    }
+
+    // Only today's value applies, fetching gets it unless `fail`
+    struct Dated {
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl DataSource for Dated {
+        type Output = u32;
+        const NAME: &'static str = "dated";
+
+        fn refresh(&self) -> Duration {
+            Duration::from_secs(60 * 60)
+        }
+
+        fn is_current(&self, output: &Self::Output) -> bool {
+            *output == 2
+        }
+
+        async fn fetch(&self) -> Result<Self::Output, Error> {
+            if self.fail {
+                Err(Error::Network("down".to_string()))
+            } else {
+                Ok(2)
+            }
+        }
+    }
+
+    // The directory goes away when the returned TempDir is dropped
+    fn temp_cache() -> (tempfile::TempDir, Cache) {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+        (dir, cache)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_all() {
+        let (_dir, cache) = temp_cache();
+        let mut registry = Registry::new(cache);
+        registry.register(Constant);
+        registry.register(Slow);
+
+        let data = registry.fetch_all().await;
+        assert_eq!(data.get::<Constant>(), Some(&42));
+        assert_eq!(data.get::<Slow>(), None);
+        assert!(
+            matches!(&data.results.get("slow").unwrap().error, Some(Error::Network(e)) if e.starts_with("Timeout"))
+        );
+        assert!(data.stale_age::<Constant>().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failed_fetch_serves_cache() {
+        let (_dir, cache) = temp_cache();
+        let stale = Utc::now() - chrono::Duration::days(1);
+        cache.store(Slow::NAME, stale, &7u32).unwrap();
+
+        let mut registry = Registry::new(cache);
+        registry.register(Slow);
+
+        let data = registry.fetch_all().await;
+        assert_eq!(data.get::<Slow>(), Some(&7));
+        assert!(data.stale_age::<Slow>().unwrap() >= Duration::from_secs(24 * 60 * 60));
+        assert_eq!(
+            data.errors().map(|(name, _)| name).collect::<Vec<_>>(),
+            ["slow"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_out_of_date_cache_is_a_miss() {
+        let (_dir, cache) = temp_cache();
+        // Fresh, but for yesterday
+        cache.store(Dated::NAME, Utc::now(), &1u32).unwrap();
+
+        let mut registry = Registry::new(cache.clone());
+        registry.register(Dated { fail: true });
+        let data = registry.fetch_all().await;
+        assert_eq!(data.get::<Dated>(), None);
+        assert_eq!(data.errors().count(), 1);
+
+        let mut registry = Registry::new(cache);
+        registry.register(Dated { fail: false });
+        let data = registry.fetch_all().await;
+        assert_eq!(data.get::<Dated>(), Some(&2));
+    }
+
+    #[tokio::test]
+    async fn test_check_and_fetch_json() {
+        let (_dir, cache) = temp_cache();
+        let mut registry = Registry::new(cache);
+        registry.register(Constant);
+        registry.register(Slow);
+
+        let checks = registry.check();
+        assert!(checks[0].1.is_ok());
+        assert!(matches!(checks[1], ("slow", Err(Error::Config(_)))));
+
+        let value = registry.fetch_json("constant").await.unwrap();
+        assert_eq!(value, serde_json::json!(42));
+        assert!(registry.fetch_json("slow").await.is_err());
+        assert!(registry.fetch_json("missing").await.is_err());
+    }
+}
@@ -1,27 +1,23 @@
 pub mod tides;
 
-use chrono::{Datelike, Local};
+use async_trait::async_trait;
+use chrono::{Datelike, Utc};
+use chrono_tz::Tz;
 
-use log::{info, warn};
-use std::time::Instant;
-use tides::Tide;
-
-use async_std::future;
-use std::time::Duration as stdDuration;
+use crate::config::Config;
+use crate::error::Error;
+use crate::source::DataSource;
 
-#[derive(Debug)]
-pub struct Stats {
-    pub tides: Option<(Tide, Tide)>,
-    pub moon_phase: f64,
-}
+use std::time::Duration;
+use tides::Tide;
 
 impl TryFrom<&str> for Tide {
-    type Error = Box<dyn std::error::Error>;
+    type Error = Error;
 
     fn try_from(line: &str) -> Result<Self, Self::Error> {
         let parts: Vec<&str> = line.split('\t').collect();
         if parts.len() < 3 {
-            return Err(format!("not 3 parts").into());
+            return Err(Error::Parse(format!("the tide \"{line}\", not 3 parts")));
         }
 
         let time = parts[0].to_string();
@@ -30,37 +26,39 @@ impl TryFrom<&str> for Tide {
         match tide_type {
             "bajamar" => Ok(Tide::Low(time)),
             "pleamar" => Ok(Tide::High(time)),
-            _ => Err(format!("not 3 bajamar/pleamar").into()),
+            _ => Err(Error::Parse(format!(
+                "the tide \"{line}\", not bajamar/pleamar"
+            ))),
         }
     }
 }
 
-pub async fn fetch_stats() -> Result<Stats, Box<dyn std::error::Error>> {
-    info!("Fetching statistics...");
-    let now = Instant::now();
-
-    let timeout = stdDuration::from_secs(25);
+pub struct MoonSource {
+    timezone: Tz,
+}
 
-    let t = future::timeout(timeout, tides::fetch()).await;
+impl MoonSource {
+    pub fn new(config: &Config) -> Self {
+        MoonSource {
+            timezone: config.timezone,
+        }
+    }
+}
 
-    let t = match t {
-        Ok(r) => r,
-        Err(e) => Err(format!("Timeout: {e}").into()),
-    };
+#[async_trait]
+impl DataSource for MoonSource {
+    // (0 = new moon, 0.5 = full moon)
+    type Output = f64;
+    const NAME: &'static str = "moon";
 
-    match &t {
-        Ok(_) => {}
-        Err(e) => warn!("Tides stats failed: {e}"),
+    fn refresh(&self) -> Duration {
+        Duration::from_secs(6 * 60 * 60)
     }
 
-    let elapsed = format!("{:.2?}", now.elapsed());
-    info!("Statistics took {elapsed}");
-
-    let now = Local::now();
-    Ok(Stats {
-        tides: t.ok(),
-        moon_phase: get_moon_phase_fraction(now.year(), now.month(), now.day()),
-    })
+    async fn fetch(&self) -> Result<Self::Output, Error> {
+        let now = Utc::now().with_timezone(&self.timezone);
+        Ok(get_moon_phase_fraction(now.year(), now.month(), now.day()))
+    }
 }
 
 fn get_moon_phase_fraction(year: i32, month: u32, day: u32) -> f64 {
@@ -70,7 +68,7 @@ fn get_moon_phase_fraction(year: i32, month: u32, day: u32) -> f64 {
     if r > 9 {
         r -= 19;
     }
-    let mut t = ((r * 11) as i32 + month as i32 + day as i32) % 30;
+    let mut t = ((r * 11) + month as i32 + day as i32) % 30;
     if t < 0 {
         t += 30;
     }
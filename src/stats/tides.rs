@@ -1,54 +1,94 @@
-extern crate reqwest;
 use std::str::FromStr;
 
-use reqwest::header;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 
-use chrono::{Local, NaiveTime, Utc};
+use chrono::{NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
 
-fn get_date() -> String {
-    let today = Utc::now().naive_utc();
-    today.format("%Y%m%d").to_string()
+use crate::config::{Config, TidesConfig};
+use crate::error::Error;
+use crate::http::Http;
+use crate::source::DataSource;
+use std::time::Duration;
+
+fn today(tz: Tz) -> NaiveDate {
+    Utc::now().with_timezone(&tz).date_naive()
+}
+
+pub struct TidesSource {
+    config: TidesConfig,
+    timezone: Tz,
+    http: Http,
+}
+
+impl TidesSource {
+    pub fn new(config: &Config, http: &Http) -> Self {
+        TidesSource {
+            http: http.clone(),
+            config: config.tides.clone(),
+            timezone: config.timezone,
+        }
+    }
+}
+
+#[async_trait]
+impl DataSource for TidesSource {
+    // The whole day's table, the next two tides are picked when rendering
+    type Output = TideTable;
+    const NAME: &'static str = "tides";
+
+    fn refresh(&self) -> Duration {
+        Duration::from_secs(3 * 60 * 60)
+    }
+
+    // Yesterday's table is no use after midnight, however fresh
+    fn is_current(&self, output: &Self::Output) -> bool {
+        output.date == today(self.timezone)
+    }
+
+    async fn fetch(&self) -> Result<Self::Output, Error> {
+        fetch(&self.config, self.timezone, &self.http).await
+    }
 }
 
-pub async fn fetch() -> Result<(Tide, Tide), Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert(
-        "User-Agent",
-        "Mozilla/5.0 (X11; Linux x86_64; rv:126.0) Gecko/20100101 Firefox/126.0".parse()?,
-    );
-
-    let client = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .build()?;
-
-    let date = get_date();
-
-    let response = client
-        .get(format!(
-            "https://ideihm.covam.es/api-ihm/getmarea?request=gettide&id=53&date={date}"
-        ))
-        .headers(headers)
-        .send()
+pub async fn fetch(config: &TidesConfig, tz: Tz, http: &Http) -> Result<TideTable, Error> {
+    let date = today(tz);
+    let port = config.port_id;
+
+    let response = http
+        .send(http.get(format!(
+            "https://ideihm.covam.es/api-ihm/getmarea?request=gettide&id={port}&date={}",
+            date.format("%Y%m%d")
+        )))
         .await?;
 
-    let response = response.error_for_status()?;
-    let data: String = response.text().await?;
+    let data: String = response.text()?;
 
     let lines: Vec<&str> = data.lines().collect();
-    let parsed: Vec<TideEntry> = lines.iter().filter_map(|&line| parse_line(line)).collect();
-    get_two_tides(&parsed, Local::now().time())
+    Ok(TideTable {
+        date,
+        entries: lines.iter().filter_map(|&line| parse_line(line)).collect(),
+    })
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Tide {
     Low(String),
     High(String),
 }
 
-#[derive(Debug, Clone)]
-struct TideEntry {
-    time: NaiveTime,
-    tide: Tide,
+/// The tides of one day, on the local clock
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TideTable {
+    pub date: NaiveDate,
+    pub entries: Vec<TideEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TideEntry {
+    pub time: NaiveTime,
+    pub tide: Tide,
 }
 
 fn parse_line(line: &str) -> Option<TideEntry> {
@@ -70,12 +110,12 @@ fn parse_line(line: &str) -> Option<TideEntry> {
     Some(TideEntry { time, tide })
 }
 
-fn get_two_tides(
-    tides: &[TideEntry],
-    ref_time: NaiveTime,
-) -> Result<(Tide, Tide), Box<dyn std::error::Error>> {
+pub fn get_two_tides(tides: &[TideEntry], ref_time: NaiveTime) -> Result<(Tide, Tide), Error> {
     if tides.len() < 2 {
-        return Err("Not enough tides overall".into());
+        return Err(Error::Parse(format!(
+            "the tide table, only {} tides in it",
+            tides.len()
+        )));
     }
 
     // If the first tide is after ref_time, return first two tides
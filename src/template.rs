@@ -0,0 +1,682 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/*
+    A tiny jinja-like template engine for the dashboard svg.
+
+        {{ day.max | fixed(1) | default("NA") }}   output, xml escaped unless piped through `raw`
+        {% for day in weather.days %} ... {% endfor %}   with `loop.index` and `loop.index0`
+        {% if tides %} ... {% else %} ... {% endif %}   `{% if not x %}` works too
+
+    Missing data is `Value::None`: it renders as nothing, is falsy, loops zero times, and any
+    field of it is None again, so `default(...)` can fill in for it.
+
+    Mistakes in the template on the other hand are errors: referencing a binding that is not
+    bound, binding something the template never uses, unknown filters or fields.
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    None,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    /// Trusted svg markup, inserted without escaping
+    Raw(String),
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+    pub fn map<K: Into<String>>(entries: impl IntoIterator<Item = (K, Value)>) -> Value {
+        Value::Map(entries.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    fn truthy(&self) -> bool {
+        match self {
+            Value::None => false,
+            Value::Bool(b) => *b,
+            Value::Number(n) => *n != 0.0,
+            Value::Str(s) | Value::Raw(s) => !s.is_empty(),
+            Value::List(l) => !l.is_empty(),
+            Value::Map(_) => true,
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::None => "none",
+            Value::Bool(_) => "bool",
+            Value::Number(_) => "number",
+            Value::Str(_) => "string",
+            Value::Raw(_) => "raw",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(n: f32) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(n: u32) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(o: Option<T>) -> Self {
+        match o {
+            Some(v) => v.into(),
+            None => Value::None,
+        }
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(v: Vec<T>) -> Self {
+        Value::List(v.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Top level names a template is rendered with
+#[derive(Debug, Default)]
+pub struct Bindings {
+    values: BTreeMap<String, Value>,
+}
+
+impl Bindings {
+    pub fn insert(&mut self, name: &str, value: impl Into<Value>) {
+        self.values.insert(name.to_string(), value.into());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Number(f64),
+    Str(String),
+}
+
+#[derive(Debug)]
+enum Filter {
+    /// Number with this many decimals
+    Fixed(usize),
+    /// Integer zero padded to this width
+    Pad(usize),
+    Default(Literal),
+    Add(f64),
+    Mul(f64),
+    Upper,
+    Raw,
+}
+
+#[derive(Debug)]
+struct Expr {
+    path: Vec<String>,
+    filters: Vec<Filter>,
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Output {
+        line: usize,
+        expr: Expr,
+    },
+    If {
+        line: usize,
+        negate: bool,
+        path: Vec<String>,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    For {
+        line: usize,
+        var: String,
+        path: Vec<String>,
+        body: Vec<Node>,
+    },
+}
+
+#[derive(Debug)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+enum Token<'a> {
+    Text(&'a str),
+    Output(usize, &'a str),
+    Tag(usize, &'a str),
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token<'_>>, String> {
+    let mut tokens = vec![];
+    let mut rest = src;
+    let mut line = 1;
+
+    loop {
+        let next = [rest.find("{{"), rest.find("{%")]
+            .into_iter()
+            .flatten()
+            .min();
+        let Some(start) = next else {
+            if !rest.is_empty() {
+                tokens.push(Token::Text(rest));
+            }
+            return Ok(tokens);
+        };
+
+        let (text, tag) = rest.split_at(start);
+        if !text.is_empty() {
+            tokens.push(Token::Text(text));
+        }
+        line += text.matches('\n').count();
+
+        let close = if tag.starts_with("{{") { "}}" } else { "%}" };
+        let end = tag
+            .find(close)
+            .ok_or(format!("line {line}: unclosed {}", &tag[..2]))?;
+        let inner = tag[2..end].trim();
+        if close == "}}" {
+            tokens.push(Token::Output(line, inner));
+        } else {
+            tokens.push(Token::Tag(line, inner));
+        }
+        line += tag[..end].matches('\n').count();
+        rest = &tag[end + 2..];
+    }
+}
+
+fn parse_path(line: usize, s: &str) -> Result<Vec<String>, String> {
+    let path: Vec<String> = s.trim().split('.').map(|p| p.to_string()).collect();
+    let valid = |p: &String| {
+        !p.is_empty()
+            && !p.starts_with(|c: char| c.is_ascii_digit())
+            && p.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    if !path.iter().all(valid) {
+        return Err(format!("line {line}: invalid name \"{}\"", s.trim()));
+    }
+    Ok(path)
+}
+
+fn parse_literal(line: usize, s: &str) -> Result<Literal, String> {
+    let s = s.trim();
+    if let Some(inner) = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        return Ok(Literal::Str(inner.to_string()));
+    }
+    s.parse::<f64>()
+        .map(Literal::Number)
+        .map_err(|_| format!("line {line}: invalid argument {s}"))
+}
+
+fn parse_filter(line: usize, s: &str) -> Result<Filter, String> {
+    let s = s.trim();
+    let (name, arg) = match s.split_once('(') {
+        Some((name, rest)) => {
+            let arg = rest
+                .strip_suffix(')')
+                .ok_or(format!("line {line}: unclosed ( in filter {s}"))?;
+            (name.trim(), Some(parse_literal(line, arg)?))
+        }
+        None => (s, None),
+    };
+
+    let number = |arg: Option<Literal>| match arg {
+        Some(Literal::Number(n)) => Ok(n),
+        _ => Err(format!(
+            "line {line}: filter {name} needs a number argument"
+        )),
+    };
+
+    match name {
+        "fixed" => Ok(Filter::Fixed(number(arg)? as usize)),
+        "pad" => Ok(Filter::Pad(number(arg)? as usize)),
+        "add" => Ok(Filter::Add(number(arg)?)),
+        "mul" => Ok(Filter::Mul(number(arg)?)),
+        "default" => Ok(Filter::Default(
+            arg.ok_or(format!("line {line}: filter default needs an argument"))?,
+        )),
+        "upper" => Ok(Filter::Upper),
+        "raw" => Ok(Filter::Raw),
+        _ => Err(format!("line {line}: unknown filter {name}")),
+    }
+}
+
+fn parse_expr(line: usize, s: &str) -> Result<Expr, String> {
+    let mut parts = s.split('|');
+    let path = parse_path(line, parts.next().unwrap_or_default())?;
+    let filters = parts
+        .map(|f| parse_filter(line, f))
+        .collect::<Result<_, _>>()?;
+    Ok(Expr { path, filters })
+}
+
+// What ended a block, so the caller can tell `else` from `endif`
+enum End {
+    Eof,
+    Tag(usize, String),
+}
+
+fn parse_nodes<'a>(
+    tokens: &mut impl Iterator<Item = Token<'a>>,
+) -> Result<(Vec<Node>, End), String> {
+    let mut nodes = vec![];
+
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Text(text) => nodes.push(Node::Text(text.to_string())),
+            Token::Output(line, inner) => nodes.push(Node::Output {
+                line,
+                expr: parse_expr(line, inner)?,
+            }),
+            Token::Tag(line, inner) => {
+                let words: Vec<&str> = inner.split_whitespace().collect();
+                match words.as_slice() {
+                    ["if", "not", path] | ["if", path] => {
+                        let negate = words.len() == 3;
+                        let path = parse_path(line, path)?;
+                        let (then, end) = parse_nodes(tokens)?;
+                        let otherwise = match end {
+                            End::Tag(_, tag) if tag == "endif" => vec![],
+                            End::Tag(_, tag) if tag == "else" => match parse_nodes(tokens)? {
+                                (otherwise, End::Tag(_, tag)) if tag == "endif" => otherwise,
+                                _ => return Err(format!("line {line}: if without endif")),
+                            },
+                            _ => return Err(format!("line {line}: if without endif")),
+                        };
+                        nodes.push(Node::If {
+                            line,
+                            negate,
+                            path,
+                            then,
+                            otherwise,
+                        });
+                    }
+                    ["for", var, "in", path] => {
+                        let var = parse_path(line, var)?.join(".");
+                        let path = parse_path(line, path)?;
+                        let body = match parse_nodes(tokens)? {
+                            (body, End::Tag(_, tag)) if tag == "endfor" => body,
+                            _ => return Err(format!("line {line}: for without endfor")),
+                        };
+                        nodes.push(Node::For {
+                            line,
+                            var,
+                            path,
+                            body,
+                        });
+                    }
+                    ["else"] | ["endif"] | ["endfor"] => {
+                        return Ok((nodes, End::Tag(line, inner.to_string())))
+                    }
+                    _ => return Err(format!("line {line}: unknown tag {{% {inner} %}}")),
+                }
+            }
+        }
+    }
+
+    Ok((nodes, End::Eof))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else {
+        format!("{n}")
+    }
+}
+
+struct Scope<'a> {
+    bindings: &'a Bindings,
+    locals: Vec<(String, Value)>,
+}
+
+impl Scope<'_> {
+    fn lookup(&self, line: usize, path: &[String]) -> Result<Value, String> {
+        let name = &path[0];
+        let mut value = match self.locals.iter().rev().find(|(n, _)| n == name) {
+            Some((_, v)) => v.clone(),
+            None => self
+                .bindings
+                .values
+                .get(name)
+                .cloned()
+                .ok_or(format!("line {line}: unknown binding {name}"))?,
+        };
+
+        for field in &path[1..] {
+            value = match value {
+                Value::None => Value::None,
+                Value::Map(mut map) => map.remove(field).ok_or(format!(
+                    "line {line}: {} has no field {field}",
+                    path.join(".")
+                ))?,
+                other => {
+                    return Err(format!(
+                        "line {line}: cannot get {field} of {} in {}",
+                        other.type_name(),
+                        path.join(".")
+                    ))
+                }
+            };
+        }
+        Ok(value)
+    }
+}
+
+fn apply(line: usize, filter: &Filter, value: Value) -> Result<Value, String> {
+    let not_a_number = |v: &Value| {
+        format!(
+            "line {line}: {filter:?} expects a number, got {}",
+            v.type_name()
+        )
+    };
+
+    Ok(match (filter, value) {
+        (Filter::Default(literal), Value::None) => match literal {
+            Literal::Number(n) => Value::Number(*n),
+            Literal::Str(s) => Value::Str(s.clone()),
+        },
+        (_, Value::None) => Value::None,
+        (Filter::Default(_), v) => v,
+        (Filter::Fixed(decimals), Value::Number(n)) => Value::Str(format!("{n:.decimals$}")),
+        (Filter::Pad(width), Value::Number(n)) => Value::Str(format!("{:0>width$}", n as i64)),
+        (Filter::Add(x), Value::Number(n)) => Value::Number(n + x),
+        (Filter::Mul(x), Value::Number(n)) => Value::Number(n * x),
+        (Filter::Fixed(_) | Filter::Pad(_) | Filter::Add(_) | Filter::Mul(_), v) => {
+            return Err(not_a_number(&v))
+        }
+        (Filter::Upper, Value::Str(s)) => Value::Str(s.to_uppercase()),
+        (Filter::Upper, v) => v,
+        (Filter::Raw, Value::Str(s)) => Value::Raw(s),
+        (Filter::Raw, v) => v,
+    })
+}
+
+fn render_value(line: usize, value: Value, out: &mut String) -> Result<(), String> {
+    match value {
+        Value::None => {}
+        Value::Bool(b) => out.push_str(if b { "true" } else { "false" }),
+        Value::Number(n) => out.push_str(&format_number(n)),
+        Value::Str(s) => out.push_str(&escape(&s)),
+        Value::Raw(s) => out.push_str(&s),
+        other => {
+            return Err(format!(
+                "line {line}: cannot output a {}, pick one of its fields",
+                other.type_name()
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn render_nodes(nodes: &[Node], scope: &mut Scope, out: &mut String) -> Result<(), String> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Output { line, expr } => {
+                let mut value = scope.lookup(*line, &expr.path)?;
+                for filter in &expr.filters {
+                    value = apply(*line, filter, value)?;
+                }
+                render_value(*line, value, out)?;
+            }
+            Node::If {
+                line,
+                negate,
+                path,
+                then,
+                otherwise,
+            } => {
+                let truthy = scope.lookup(*line, path)?.truthy();
+                if truthy != *negate {
+                    render_nodes(then, scope, out)?;
+                } else {
+                    render_nodes(otherwise, scope, out)?;
+                }
+            }
+            Node::For {
+                line,
+                var,
+                path,
+                body,
+            } => {
+                let items = match scope.lookup(*line, path)? {
+                    Value::None => vec![],
+                    Value::List(items) => items,
+                    other => {
+                        return Err(format!(
+                            "line {line}: cannot loop over a {} in {}",
+                            other.type_name(),
+                            path.join(".")
+                        ))
+                    }
+                };
+                for (i, item) in items.into_iter().enumerate() {
+                    let index = Value::map([
+                        ("index", Value::from(i as u32 + 1)),
+                        ("index0", Value::from(i as u32)),
+                    ]);
+                    scope.locals.push(("loop".to_string(), index));
+                    scope.locals.push((var.clone(), item));
+                    let result = render_nodes(body, scope, out);
+                    scope.locals.truncate(scope.locals.len() - 2);
+                    result?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn add_name(line: usize, path: &[String], locals: &[String], names: &mut BTreeMap<String, usize>) {
+    if !locals.contains(&path[0]) {
+        names.entry(path[0].clone()).or_insert(line);
+    }
+}
+
+// Top level names the template reads, with the first line each is used on
+fn collect_names(nodes: &[Node], locals: &mut Vec<String>, names: &mut BTreeMap<String, usize>) {
+    for node in nodes {
+        match node {
+            Node::Text(_) => {}
+            Node::Output { line, expr } => add_name(*line, &expr.path, locals, names),
+            Node::If {
+                line,
+                path,
+                then,
+                otherwise,
+                ..
+            } => {
+                add_name(*line, path, locals, names);
+                collect_names(then, locals, names);
+                collect_names(otherwise, locals, names);
+            }
+            Node::For {
+                line,
+                var,
+                path,
+                body,
+            } => {
+                add_name(*line, path, locals, names);
+                locals.push(var.clone());
+                locals.push("loop".to_string());
+                collect_names(body, locals, names);
+                locals.truncate(locals.len() - 2);
+            }
+        }
+    }
+}
+
+impl Template {
+    pub fn parse(src: &str) -> Result<Template, String> {
+        let mut tokens = tokenize(src)?.into_iter();
+        match parse_nodes(&mut tokens)? {
+            (nodes, End::Eof) => Ok(Template { nodes }),
+            (_, End::Tag(line, tag)) => Err(format!("line {line}: unexpected {{% {tag} %}}")),
+        }
+    }
+
+    /// Top level names the template reads, so the caller can skip computing the rest
+    pub fn names(&self) -> BTreeSet<String> {
+        let mut names = BTreeMap::new();
+        collect_names(&self.nodes, &mut vec![], &mut names);
+        names.into_keys().collect()
+    }
+
+    pub fn render(&self, bindings: &Bindings) -> Result<String, String> {
+        let mut used = BTreeMap::new();
+        collect_names(&self.nodes, &mut vec![], &mut used);
+
+        if let Some((name, line)) = used.iter().find(|(n, _)| !bindings.values.contains_key(*n)) {
+            return Err(format!("line {line}: unknown binding {name}"));
+        }
+        let unused: BTreeSet<&String> = bindings
+            .values
+            .keys()
+            .filter(|n| !used.contains_key(*n))
+            .collect();
+        if !unused.is_empty() {
+            let unused: Vec<&str> = unused.into_iter().map(|n| n.as_str()).collect();
+            return Err(format!(
+                "bindings never used by the template: {}",
+                unused.join(", ")
+            ));
+        }
+
+        let mut out = String::new();
+        let mut scope = Scope {
+            bindings,
+            locals: vec![],
+        };
+        render_nodes(&self.nodes, &mut scope, &mut out)?;
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(src: &str, bindings: &Bindings) -> Result<String, String> {
+        Template::parse(src)?.render(bindings)
+    }
+
+    #[test]
+    fn test_output_and_filters() {
+        let mut bindings = Bindings::default();
+        bindings.insert("temp", 23.14);
+        bindings.insert("day", 8u32);
+        bindings.insert("name", "Mié <3");
+        bindings.insert("icon", Value::Raw("<path />".to_string()));
+        bindings.insert("missing", Value::None);
+
+        let out = render(
+            r#"<text>{{ day | pad(2) }} {{ name }}</text><text>{{ temp | fixed(1) }}°</text>{{ icon }}<text>{{ missing | fixed(1) | default("NA") }}</text>"#,
+            &bindings,
+        )
+        .unwrap();
+        assert_eq!(
+            out,
+            "<text>08 Mié &lt;3</text><text>23.1°</text><path /><text>NA</text>"
+        );
+    }
+
+    #[test]
+    fn test_names_do_not_collide() {
+        let mut bindings = Bindings::default();
+        bindings.insert("I1", "first");
+        bindings.insert("I1a", "second");
+        assert_eq!(
+            render("{{ I1 }} {{ I1a }}", &bindings).unwrap(),
+            "first second"
+        );
+    }
+
+    #[test]
+    fn test_loops_and_conditionals() {
+        let mut bindings = Bindings::default();
+        bindings.insert(
+            "weather",
+            Value::map([(
+                "days",
+                Value::List(vec![Value::map([("max", Value::from(20.0))]), Value::None]),
+            )]),
+        );
+        bindings.insert("tides", Value::None);
+
+        let out = render(
+            r#"{% for day in weather.days %}<g x="{{ loop.index0 | mul(200) }}">{% if day %}{{ day.max }}{% else %}NA{% endif %}</g>{% endfor %}{% if not tides %}ERR{% endif %}"#,
+            &bindings,
+        )
+        .unwrap();
+        assert_eq!(out, r#"<g x="0">20</g><g x="200">NA</g>ERR"#);
+    }
+
+    #[test]
+    fn test_template_mistakes() {
+        let mut bindings = Bindings::default();
+        bindings.insert("time", Value::map([("hour", Value::from(9u32))]));
+
+        // Unknown binding, even in a branch that is not taken
+        let err = render(
+            "{{ time.hour }}{% if time %}{% else %}{{ tme }}{% endif %}",
+            &bindings,
+        )
+        .unwrap_err();
+        assert!(err.contains("unknown binding tme"), "{err}");
+
+        assert_eq!(
+            Template::parse("{{ time.hour }}{% for t in tides %}{{ t }}{% endfor %}")
+                .unwrap()
+                .names(),
+            BTreeSet::from(["tides".to_string(), "time".to_string()])
+        );
+
+        // Unused binding
+        let err = render("nothing", &bindings).unwrap_err();
+        assert!(err.contains("never used by the template: time"), "{err}");
+
+        // Unknown field, filter and unclosed blocks
+        assert!(render("{{ time.minute }}", &bindings).is_err());
+        assert!(Template::parse("{{ time.hour | fixd(1) }}").is_err());
+        assert!(Template::parse("{% if time %}").is_err());
+        assert!(Template::parse("{{ time.hour").is_err());
+    }
+}
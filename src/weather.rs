@@ -1,14 +1,15 @@
+use async_trait::async_trait;
 use chrono::prelude::*;
-use reqwest;
-use serde::Deserialize;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
 
-use log::info;
-use std::time::Instant;
+use crate::config::{Config, WeatherConfig};
+use crate::error::Error;
+use crate::http::Http;
+use crate::secrets::Secret;
+use crate::source::DataSource;
 
-#[derive(Deserialize, Debug)]
-struct OpenWeatherMapKey {
-    key: String,
-}
+use std::time::Duration;
 
 #[derive(Deserialize, Debug)]
 struct WeatherData {
@@ -20,11 +21,13 @@ struct Data {
     dt: i64,
     main: Main,
     rain: Option<Rain>,
+    #[serde(rename = "clouds")]
     cloud: Option<Cloud>,
 }
 
 #[derive(Deserialize, Debug)]
 struct Main {
+    temp: f64,
     temp_min: f64,
     temp_max: f64,
 }
@@ -40,104 +43,210 @@ struct Cloud {
     all: f64,
 }
 
-#[derive(Default, Debug)]
+#[derive(Serialize, Deserialize, Default, Debug)]
 pub struct DayData {
     pub data_points: i8,
-    pub date: u32,
-    pub day: String,
+    /// On the local clock, see `Config::timezone`
+    pub date: NaiveDate,
     pub rain_sum: f64,
     pub cloud_sum: f64,
     pub max_c: f64,
     pub min_c: f64,
 }
 
-// Async function to fetch weather data
-pub async fn fetch_weather() -> Result<Vec<DayData>, Box<dyn std::error::Error>> {
-    info!("Fetching weather...");
-    let now = Instant::now();
+/// One forecast point, for the hourly chart
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HourData {
+    pub time: DateTime<Utc>,
+    pub temp_c: f64,
+    /// Over the 3 hours up to `time`
+    pub rain_mm: f64,
+}
 
-    let file = std::fs::File::open("sensitive/openweatherkey.json")?;
-    let json_key: OpenWeatherMapKey = serde_json::from_reader(file)?;
-    let key = json_key.key;
-    let url = format!("http://api.openweathermap.org/data/2.5/forecast?lat=28.96302&lon=-13.54769&units=metric&appid={key}");
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Forecast {
+    pub days: Vec<DayData>,
+    /// Every point as it came, 3 hours apart
+    pub hours: Vec<HourData>,
+}
 
-    let response = reqwest::get(&url).await?;
-    let response = response.error_for_status()?;
-    let weather_data: WeatherData = response.json().await?;
-    let mut result = vec![];
+pub fn day_name(weekday: Weekday) -> String {
+    match weekday {
+        Weekday::Mon => "Lun",
+        Weekday::Tue => "Mar",
+        Weekday::Wed => "Mié",
+        Weekday::Thu => "Jue",
+        Weekday::Fri => "Vie",
+        Weekday::Sat => "Sáb",
+        Weekday::Sun => "Dom",
+    }
+    .to_string()
+}
 
-    let mut current_day = chrono::offset::Utc::now().day();
+pub struct WeatherSource {
+    config: WeatherConfig,
+    timezone: Tz,
+    http: Http,
+    key: Secret,
+}
 
-    for point in weather_data.list {
-        let point_day = DateTime::from_timestamp(point.dt, 0).ok_or("Invalid datetime")?;
-
-        if result.len() == 0 {
-            let mut data = DayData::default();
-            current_day = point_day.day();
-
-            data.date = current_day;
-            data.day = (match point_day.weekday() {
-                Weekday::Mon => "Lun",
-                Weekday::Tue => "Mar",
-                Weekday::Wed => "Mié",
-                Weekday::Thu => "Jue",
-                Weekday::Fri => "Vie",
-                Weekday::Sat => "Sáb",
-                Weekday::Sun => "Dom",
-            })
-            .to_string();
-            data.min_c = point.main.temp_min;
-            data.max_c = point.main.temp_max;
-            result.push(data);
+impl WeatherSource {
+    pub fn new(config: &Config, http: &Http, key: &Secret) -> Self {
+        WeatherSource {
+            config: config.weather.clone(),
+            timezone: config.timezone,
+            http: http.clone(),
+            key: key.clone(),
         }
+    }
+}
+
+#[async_trait]
+impl DataSource for WeatherSource {
+    type Output = Forecast;
+    const NAME: &'static str = "weather";
+    const CREDENTIALS: &'static [&'static str] = &["openweathermap"];
 
-        if point_day.day() == current_day {
-            let current = result.pop();
-
-            match current {
-                Some(mut current) => {
-                    current.data_points += 1;
-                    if point.main.temp_min < current.min_c {
-                        current.min_c = point.main.temp_min
-                    }
-
-                    current.max_c = f64::max(current.max_c, point.main.temp_max);
-                    current.rain_sum += match point.rain {
-                        Some(rain) => rain.three_h,
-                        None => 0.0,
-                    };
-                    current.cloud_sum += match point.cloud {
-                        Some(cloud) => cloud.all,
-                        None => 0.0,
-                    };
-                    result.push(current);
-                }
-                None => {
-                    panic!("No weather data - This should be impossible to reach...")
-                }
-            }
-        } else {
-            let mut data = DayData::default();
-            data.date = point_day.day();
-            data.day = (match point_day.weekday() {
-                Weekday::Mon => "Lun",
-                Weekday::Tue => "Mar",
-                Weekday::Wed => "Mié",
-                Weekday::Thu => "Jue",
-                Weekday::Fri => "Vie",
-                Weekday::Sat => "Sáb",
-                Weekday::Sun => "Dom",
-            })
-            .to_string();
-            data.min_c = f64::INFINITY;
-            data.max_c = f64::NEG_INFINITY;
-            result.push(data);
-            current_day += 1;
+    fn refresh(&self) -> Duration {
+        Duration::from_secs(60 * 60)
+    }
+
+    async fn fetch(&self) -> Result<Self::Output, Error> {
+        fetch_weather(&self.config, self.timezone, &self.http, &self.key).await
+    }
+}
+
+// Async function to fetch weather data
+pub async fn fetch_weather(
+    config: &WeatherConfig,
+    timezone: Tz,
+    http: &Http,
+    key: &Secret,
+) -> Result<Forecast, Error> {
+    let key = key.expose();
+    let url = format!(
+        "http://api.openweathermap.org/data/2.5/forecast?lat={}&lon={}&units=metric&appid={key}",
+        config.lat, config.lon
+    );
+
+    let response = http.send(http.get(&url)).await?;
+    let weather_data: WeatherData = response.json()?;
+    forecast(weather_data, timezone)
+}
+
+// Points bucketed by their date on the local clock, openweathermap sends them in order
+fn forecast(weather_data: WeatherData, timezone: Tz) -> Result<Forecast, Error> {
+    let mut days: Vec<DayData> = vec![];
+    let mut hours = vec![];
+
+    for point in weather_data.list {
+        let time = DateTime::from_timestamp(point.dt, 0)
+            .ok_or(Error::Parse(format!("the timestamp {}", point.dt)))?;
+        let rain_mm = point.rain.as_ref().map_or(0.0, |rain| rain.three_h);
+        hours.push(HourData {
+            time,
+            temp_c: point.main.temp,
+            rain_mm,
+        });
+
+        let date = time.with_timezone(&timezone).date_naive();
+        if days.last().map(|day| day.date) != Some(date) {
+            days.push(DayData {
+                date,
+                min_c: f64::INFINITY,
+                max_c: f64::NEG_INFINITY,
+                ..Default::default()
+            });
+        }
+        if let Some(day) = days.last_mut() {
+            day.data_points += 1;
+            day.min_c = f64::min(day.min_c, point.main.temp_min);
+            day.max_c = f64::max(day.max_c, point.main.temp_max);
+            day.rain_sum += rain_mm;
+            day.cloud_sum += point.cloud.map_or(0.0, |cloud| cloud.all);
         }
     }
 
-    let elapsed = format!("{:.2?}", now.elapsed());
-    info!("Weather took {elapsed}");
+    Ok(Forecast { days, hours })
+}
 
-    Ok(result)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Synthetic forecasts, the points are mostly 3 hours apart and `temp` goes up by 1 each
+    fn days(fixture: &str, timezone: Tz) -> Vec<(NaiveDate, i8, f64, f64)> {
+        let weather_data: WeatherData = serde_json::from_str(fixture).unwrap();
+        let count = weather_data.list.len();
+        let forecast = forecast(weather_data, timezone).unwrap();
+        assert_eq!(forecast.hours.len(), count);
+        forecast
+            .days
+            .iter()
+            .map(|day| (day.date, day.data_points, day.min_c, day.max_c))
+            .collect()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_month_and_year_rollover() {
+        let canary = chrono_tz::Atlantic::Canary;
+        let month_end = include_str!("testdata/forecast_month_end.json");
+        assert_eq!(
+            days(month_end, canary),
+            vec![
+                (date(2024, 1, 30), 1, 9.5, 10.5),
+                (date(2024, 1, 31), 8, 10.5, 18.5),
+                (date(2024, 2, 1), 7, 18.5, 25.5),
+            ]
+        );
+
+        let year_end = include_str!("testdata/forecast_year_end.json");
+        assert_eq!(
+            days(year_end, canary),
+            vec![
+                (date(2023, 12, 31), 8, 9.5, 17.5),
+                (date(2024, 1, 1), 4, 17.5, 21.5),
+            ]
+        );
+
+        // Rain and clouds are added up per day
+        let weather_data: WeatherData = serde_json::from_str(month_end).unwrap();
+        let forecast = forecast(weather_data, canary).unwrap();
+        let rain: Vec<f64> = forecast.days.iter().map(|day| day.rain_sum).collect();
+        assert_eq!(rain, vec![0.5, 1.0, 0.5]);
+        let clouds: Vec<f64> = forecast.days.iter().map(|day| day.cloud_sum).collect();
+        assert_eq!(clouds, vec![20.0, 160.0, 140.0]);
+    }
+
+    #[test]
+    fn test_local_days_across_dst() {
+        // Evening points belong to the day they fall in on the local clock, not in UTC. The
+        // 04:30Z points are 00:30 on the summer clock, a fixed -05:00 would put them a day early
+        let new_york = chrono_tz::America::New_York;
+        assert_eq!(
+            days(include_str!("testdata/forecast_dst_start.json"), new_york),
+            vec![
+                (date(2024, 3, 9), 6, 9.5, 15.5),
+                (date(2024, 3, 10), 8, 15.5, 23.5),
+                (date(2024, 3, 11), 3, 22.5, 25.5),
+            ]
+        );
+        assert_eq!(
+            days(include_str!("testdata/forecast_dst_end.json"), new_york),
+            vec![
+                (date(2024, 11, 2), 6, 9.5, 15.5),
+                (date(2024, 11, 3), 9, 14.5, 23.5),
+                (date(2024, 11, 4), 2, 23.5, 25.5),
+            ]
+        );
+
+        // The same points in UTC split evenly
+        let fixture = include_str!("testdata/forecast_dst_start.json");
+        let counts: Vec<i8> = days(fixture, Tz::UTC).iter().map(|day| day.1).collect();
+        assert_eq!(counts, vec![4, 8, 5]);
+    }
 }